@@ -0,0 +1,210 @@
+use crate::tree::{Node, Symbol};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+pub fn code_lengths_from_tree(root: &Node) -> HashMap<Symbol, u8> {
+    let mut lengths = HashMap::new();
+
+    // A lone leaf still needs one bit per symbol, otherwise nothing would be written.
+    if root.is_leaf() {
+        lengths.insert(root.symbol, 1);
+        return lengths;
+    }
+
+    collect_lengths_recursive(root, 0, &mut lengths);
+    lengths
+}
+
+fn collect_lengths_recursive(node: &Node, depth: u8, lengths: &mut HashMap<Symbol, u8>) {
+    if node.is_leaf() {
+        lengths.insert(node.symbol, depth);
+        return;
+    }
+
+    if let Some(ref left) = node.left {
+        collect_lengths_recursive(left, depth + 1, lengths);
+    }
+
+    if let Some(ref right) = node.right {
+        collect_lengths_recursive(right, depth + 1, lengths);
+    }
+}
+
+enum MergeItem {
+    Leaf(usize),
+    Package(usize, usize),
+}
+
+pub fn length_limited_code_lengths<S: Copy + Ord + Hash>(
+    frequencies: &[(S, u32)],
+    max_length: u8,
+) -> HashMap<S, u8> {
+    let mut leaves: Vec<(S, u64)> = frequencies
+        .iter()
+        .filter(|(_, frequency)| *frequency > 0)
+        .map(|&(symbol, frequency)| (symbol, frequency as u64))
+        .collect();
+    leaves.sort_by_key(|&(symbol, weight)| (weight, symbol));
+
+    let mut lengths = HashMap::new();
+    if leaves.len() <= 1 {
+        if let Some(&(symbol, _)) = leaves.first() {
+            lengths.insert(symbol, 1);
+        }
+        return lengths;
+    }
+
+    // Package-merge needs 2^max_length >= symbol count. Encoders reject tighter limits up
+    // front; raising it here keeps package-merge from running out of items when they do not.
+    let max_length = max_length.max(bits_needed(leaves.len() as u32 - 1) as u8);
+
+    let mut items: Vec<MergeItem> = (0..leaves.len()).map(MergeItem::Leaf).collect();
+    let mut weights: Vec<u64> = leaves.iter().map(|&(_, weight)| weight).collect();
+    let leaf_ids: Vec<usize> = (0..leaves.len()).collect();
+
+    let mut current = leaf_ids.clone();
+    for _ in 1..max_length {
+        let mut packages = Vec::with_capacity(current.len() / 2);
+        for pair in current.chunks_exact(2) {
+            items.push(MergeItem::Package(pair[0], pair[1]));
+            weights.push(weights[pair[0]] + weights[pair[1]]);
+            packages.push(items.len() - 1);
+        }
+
+        let mut merged = Vec::with_capacity(leaf_ids.len() + packages.len());
+        let (mut i, mut j) = (0, 0);
+        while i < leaf_ids.len() || j < packages.len() {
+            let take_leaf = j >= packages.len()
+                || (i < leaf_ids.len() && weights[leaf_ids[i]] <= weights[packages[j]]);
+
+            if take_leaf {
+                merged.push(leaf_ids[i]);
+                i += 1;
+            } else {
+                merged.push(packages[j]);
+                j += 1;
+            }
+        }
+
+        current = merged;
+    }
+
+    let mut leaf_lengths = vec![0u8; leaves.len()];
+    let mut stack: Vec<usize> = current[..2 * leaves.len() - 2].to_vec();
+    while let Some(id) = stack.pop() {
+        match items[id] {
+            MergeItem::Leaf(leaf) => leaf_lengths[leaf] += 1,
+            MergeItem::Package(first, second) => {
+                stack.push(first);
+                stack.push(second);
+            }
+        }
+    }
+
+    for (leaf, &(symbol, _)) in leaves.iter().enumerate() {
+        lengths.insert(symbol, leaf_lengths[leaf]);
+    }
+
+    lengths
+}
+
+/// Shannon-Fano lengths: symbols sorted by falling frequency are split recursively where the
+/// totals of the two halves are closest, and each split adds one bit to every symbol below it.
+pub fn shannon_fano_code_lengths<S: Copy + Ord + Hash>(frequencies: &[(S, u32)]) -> HashMap<S, u8> {
+    let mut leaves: Vec<(S, u64)> = frequencies
+        .iter()
+        .filter(|(_, frequency)| *frequency > 0)
+        .map(|&(symbol, frequency)| (symbol, frequency as u64))
+        .collect();
+    leaves.sort_by_key(|&(symbol, weight)| (std::cmp::Reverse(weight), symbol));
+
+    let mut lengths = HashMap::new();
+    if leaves.len() <= 1 {
+        if let Some(&(symbol, _)) = leaves.first() {
+            lengths.insert(symbol, 1);
+        }
+        return lengths;
+    }
+
+    // prefix[i] is the total weight of the first i leaves.
+    let prefix: Vec<u64> = std::iter::once(0)
+        .chain(leaves.iter().scan(0, |total, &(_, weight)| {
+            *total += weight;
+            Some(*total)
+        }))
+        .collect();
+
+    let mut stack = vec![(0, leaves.len(), 0u8)];
+    while let Some((start, end, depth)) = stack.pop() {
+        if end - start == 1 {
+            lengths.insert(leaves[start].0, depth);
+            continue;
+        }
+
+        let total = prefix[end] - prefix[start];
+        let split = (start + 1..end)
+            .min_by_key(|&split| (2 * (prefix[split] - prefix[start])).abs_diff(total))
+            .unwrap_or(start + 1);
+
+        stack.push((start, split, depth + 1));
+        stack.push((split, end, depth + 1));
+    }
+
+    lengths
+}
+
+pub fn bits_needed(value: u32) -> u32 {
+    32 - value.leading_zeros()
+}
+
+/// Whether a prefix code with these lengths exists, i.e. the Kraft sum is at most one.
+pub fn satisfies_kraft<S>(lengths: &HashMap<S, u8>) -> bool {
+    let whole = 1u128 << 64;
+    let sum: u128 = lengths
+        .values()
+        .filter(|&&length| length > 0)
+        .map(|&length| whole >> length.min(64))
+        .sum();
+
+    sum <= whole
+}
+
+/// The Kraft sum of these lengths: one for a complete prefix code, below one when code space is unused.
+pub fn kraft_sum<S>(lengths: &HashMap<S, u8>) -> f64 {
+    lengths
+        .values()
+        .filter(|&&length| length > 0)
+        .map(|&length| 0.5f64.powi(length as i32))
+        .sum()
+}
+
+fn sorted_by_length<S: Copy + Ord>(lengths: &HashMap<S, u8>) -> Vec<(S, u8)> {
+    let mut ordered: Vec<(S, u8)> = lengths
+        .iter()
+        .filter(|(_, &length)| length > 0)
+        .map(|(&symbol, &length)| (symbol, length))
+        .collect();
+
+    ordered.sort_by_key(|&(symbol, length)| (length, symbol));
+    ordered
+}
+
+pub fn assign_canonical_codes<S: Copy + Ord + Hash>(
+    lengths: &HashMap<S, u8>,
+) -> HashMap<S, Vec<bool>> {
+    let mut codes = HashMap::new();
+    let mut code = 0u64;
+    let mut previous_length = 0u8;
+
+    for (symbol, length) in sorted_by_length(lengths) {
+        code <<= length - previous_length;
+
+        let bits = (0..length).rev().map(|i| (code >> i) & 1 == 1).collect();
+        codes.insert(symbol, bits);
+
+        code += 1;
+        previous_length = length;
+    }
+
+    codes
+}
@@ -0,0 +1,5 @@
+//! Huffman symbols, trees and canonical code construction without any web server
+//! dependencies, shared by the Huffman coder and the DEFLATE mode of the LZ77 coder.
+
+pub mod canonical;
+pub mod tree;
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Symbol {
+    OneByte(u8),
+    TwoBytes(u16),
+    /// A byte together with the byte that preceded it, which selects its code table.
+    Context(u8, u8),
+    /// Index of a token in the word dictionary stored alongside the codes.
+    Word(u32),
+    /// A Unicode scalar value, however many bytes its UTF-8 encoding takes.
+    CodePoint(char),
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Symbol::OneByte(byte) => write!(f, "{}", describe_byte(*byte)),
+            Symbol::TwoBytes(val) => write!(f, "bytes 0x{:04X}", val),
+            Symbol::Context(previous, byte) => write!(
+                f,
+                "{} after {}",
+                describe_byte(*byte),
+                describe_byte(*previous)
+            ),
+            Symbol::Word(id) => write!(f, "word #{}", id),
+            Symbol::CodePoint(c) => write!(f, "U+{:04X} {:?}", *c as u32, c),
+        }
+    }
+}
+
+fn describe_byte(byte: u8) -> String {
+    if (32..=126).contains(&byte) {
+        format!("{} (0x{:02X})", byte as char, byte)
+    } else {
+        format!("byte 0x{:02X}", byte)
+    }
+}
+
+#[derive(Clone, Debug, Eq)]
+pub struct Node {
+    pub frequency: u32,
+    pub symbol: Symbol,
+    pub left: Option<Box<Node>>,
+    pub right: Option<Box<Node>>,
+}
+
+impl Node {
+    pub fn new(symbol: Symbol, frequency: u32) -> Self {
+        Self {
+            frequency,
+            symbol,
+            left: None,
+            right: None,
+        }
+    }
+
+    pub fn new_parent(left: Node, right: Node) -> Self {
+        Self {
+            frequency: left.frequency + right.frequency,
+            symbol: left.symbol,
+            left: Some(Box::new(left)),
+            right: Some(Box::new(right)),
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.left.is_none() && self.right.is_none()
+    }
+
+    /// Codes read off the tree itself, 0 for left and 1 for right, as legacy files use them.
+    pub fn path_codes(&self) -> HashMap<Symbol, Vec<bool>> {
+        let mut codes = HashMap::new();
+        let mut stack = vec![(self, Vec::new())];
+
+        while let Some((node, path)) = stack.pop() {
+            if node.is_leaf() {
+                codes.insert(node.symbol, path);
+                continue;
+            }
+
+            for (child, bit) in [(&node.left, false), (&node.right, true)] {
+                if let Some(child) = child {
+                    let mut child_path = path.clone();
+                    child_path.push(bit);
+                    stack.push((child, child_path));
+                }
+            }
+        }
+
+        codes
+    }
+
+    /// Renders the tree as a Graphviz digraph: internal nodes show their frequency, leaves
+    /// their symbol and frequency, and edges the bit they stand for.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph huffman {\n    node [shape=circle];\n");
+        let mut next_id = 0;
+        self.write_dot(&mut dot, &mut next_id);
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn write_dot(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+
+        if self.is_leaf() {
+            let label = self
+                .symbol
+                .to_string()
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            dot.push_str(&format!(
+                "    n{} [shape=box, label=\"{}\\n{}\"];\n",
+                id, label, self.frequency
+            ));
+        } else {
+            dot.push_str(&format!("    n{} [label=\"{}\"];\n", id, self.frequency));
+        }
+
+        for (child, bit) in [(&self.left, 0), (&self.right, 1)] {
+            if let Some(child) = child {
+                let child_id = child.write_dot(dot, next_id);
+                dot.push_str(&format!(
+                    "    n{} -> n{} [label=\"{}\"];\n",
+                    id, child_id, bit
+                ));
+            }
+        }
+
+        id
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.frequency == other.frequency
+    }
+}
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match other.frequency.cmp(&self.frequency) {
+            std::cmp::Ordering::Equal => other.symbol.cmp(&self.symbol),
+            other_ordering => other_ordering,
+        }
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
@@ -0,0 +1,315 @@
+use crate::bit_operations::{BitReader, BitWriter};
+use crate::error::HuffmanError;
+use crate::tree::Symbol;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+struct AdaptiveNode {
+    weight: u64,
+    parent: Option<usize>,
+    left: Option<usize>,
+    right: Option<usize>,
+    symbol: Option<Symbol>,
+}
+
+impl AdaptiveNode {
+    fn new_leaf(symbol: Option<Symbol>, parent: Option<usize>) -> Self {
+        Self {
+            weight: 0,
+            parent,
+            left: None,
+            right: None,
+            symbol,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.left.is_none() && self.right.is_none()
+    }
+}
+
+/// Vitter's algorithm (Lambda). Nodes are kept in `order` from the root (rank 0) downwards,
+/// so every block of equal weight is contiguous with internal nodes ranked ahead of leaves
+/// and a block leader can be found by binary search.
+pub struct AdaptiveHuffman {
+    symbol: Symbol,
+    nodes: Vec<AdaptiveNode>,
+    order: Vec<usize>,
+    rank: Vec<usize>,
+    leaves: HashMap<Symbol, usize>,
+    nyt: usize,
+}
+
+impl AdaptiveHuffman {
+    pub fn new(symbol: Symbol) -> Self {
+        Self {
+            symbol,
+            nodes: vec![AdaptiveNode::new_leaf(None, None)],
+            order: vec![0],
+            rank: vec![0],
+            leaves: HashMap::new(),
+            nyt: 0,
+        }
+    }
+
+    fn symbol_bits(&self) -> u32 {
+        match self.symbol {
+            Symbol::OneByte(_) | Symbol::Context(..) => 8,
+            Symbol::TwoBytes(_) => 16,
+            Symbol::Word(_) => 32,
+            Symbol::CodePoint(_) => 21,
+        }
+    }
+
+    fn symbol_value(symbol: Symbol) -> u32 {
+        match symbol {
+            Symbol::OneByte(byte) => byte as u32,
+            Symbol::TwoBytes(value) => value as u32,
+            Symbol::Context(_, byte) => byte as u32,
+            Symbol::Word(id) => id,
+            Symbol::CodePoint(c) => c as u32,
+        }
+    }
+
+    /// Returns `None` for values that are not Unicode scalar values in code point mode.
+    fn symbol_from_value(&self, value: u32) -> Option<Symbol> {
+        match self.symbol {
+            Symbol::OneByte(_) => Some(Symbol::OneByte(value as u8)),
+            Symbol::TwoBytes(_) => Some(Symbol::TwoBytes(value as u16)),
+            Symbol::Context(previous, _) => Some(Symbol::Context(previous, value as u8)),
+            Symbol::Word(_) => Some(Symbol::Word(value)),
+            Symbol::CodePoint(_) => char::from_u32(value).map(Symbol::CodePoint),
+        }
+    }
+
+    pub fn encode_symbol(&mut self, symbol: Symbol, writer: &mut BitWriter) {
+        match self.leaves.get(&symbol) {
+            Some(&leaf) => self.write_path(leaf, writer),
+            None => {
+                self.write_path(self.nyt, writer);
+                writer.write_bit(false);
+                writer.write_n_bits(Self::symbol_value(symbol), self.symbol_bits());
+            }
+        }
+
+        self.update(symbol);
+    }
+
+    /// The NYT escape followed by a set flag bit marks the end of the stream.
+    pub fn encode_end(&self, writer: &mut BitWriter) {
+        self.write_path(self.nyt, writer);
+        writer.write_bit(true);
+    }
+
+    /// Returns `Ok(None)` once the end-of-stream marker has been read.
+    pub fn decode_symbol(
+        &mut self,
+        reader: &mut BitReader,
+    ) -> Result<Option<Symbol>, HuffmanError> {
+        let mut node = self.order[0];
+
+        while !self.nodes[node].is_leaf() {
+            let bit = reader.read_bit().ok_or(HuffmanError::PrematureEnd)?;
+            node = if bit {
+                self.nodes[node].right
+            } else {
+                self.nodes[node].left
+            }
+            .ok_or(HuffmanError::InvalidTree)?;
+        }
+
+        let symbol = match self.nodes[node].symbol {
+            Some(symbol) => symbol,
+            None => {
+                if reader.read_bit().ok_or(HuffmanError::PrematureEnd)? {
+                    return Ok(None);
+                }
+                let value = reader
+                    .read_n_bits(self.symbol_bits())
+                    .ok_or(HuffmanError::PrematureEnd)?;
+                self.symbol_from_value(value)
+                    .ok_or(HuffmanError::InvalidCode)?
+            }
+        };
+
+        self.update(symbol);
+        Ok(Some(symbol))
+    }
+
+    pub fn get_codes(&self) -> HashMap<Symbol, Vec<bool>> {
+        self.leaves
+            .iter()
+            .map(|(&symbol, &leaf)| (symbol, self.path_to(leaf)))
+            .collect()
+    }
+
+    fn path_to(&self, node: usize) -> Vec<bool> {
+        let mut path = Vec::new();
+        let mut current = node;
+
+        while let Some(parent) = self.nodes[current].parent {
+            path.push(self.nodes[parent].right == Some(current));
+            current = parent;
+        }
+
+        path.reverse();
+        path
+    }
+
+    fn write_path(&self, node: usize, writer: &mut BitWriter) {
+        for bit in self.path_to(node) {
+            writer.write_bit(bit);
+        }
+    }
+
+    fn update(&mut self, symbol: Symbol) {
+        let mut leaf_to_increment = None;
+
+        let mut q = match self.leaves.get(&symbol) {
+            None => {
+                let new_leaf = self.split_nyt(symbol);
+                leaf_to_increment = Some(new_leaf);
+                self.nodes[new_leaf].parent
+            }
+            Some(&leaf) => {
+                let leader = self.block_leader(leaf);
+                self.swap_nodes(leaf, leader);
+
+                let parent = self.nodes[leaf].parent;
+                let is_nyt_sibling = parent
+                    .map(|parent| {
+                        self.nodes[parent].left == Some(self.nyt)
+                            || self.nodes[parent].right == Some(self.nyt)
+                    })
+                    .unwrap_or(false);
+
+                if is_nyt_sibling {
+                    leaf_to_increment = Some(leaf);
+                    parent
+                } else {
+                    Some(leaf)
+                }
+            }
+        };
+
+        while let Some(node) = q {
+            q = self.slide_and_increment(node);
+        }
+
+        if let Some(leaf) = leaf_to_increment {
+            self.slide_and_increment(leaf);
+        }
+    }
+
+    fn split_nyt(&mut self, symbol: Symbol) -> usize {
+        let old_nyt = self.nyt;
+        let new_leaf = self.nodes.len();
+        let new_nyt = new_leaf + 1;
+
+        self.nodes
+            .push(AdaptiveNode::new_leaf(Some(symbol), Some(old_nyt)));
+        self.nodes.push(AdaptiveNode::new_leaf(None, Some(old_nyt)));
+        self.nodes[old_nyt].left = Some(new_nyt);
+        self.nodes[old_nyt].right = Some(new_leaf);
+
+        self.rank.push(self.order.len());
+        self.order.push(new_leaf);
+        self.rank.push(self.order.len());
+        self.order.push(new_nyt);
+
+        self.leaves.insert(symbol, new_leaf);
+        self.nyt = new_nyt;
+
+        new_leaf
+    }
+
+    fn block_key(&self, node: usize) -> (Reverse<u64>, bool) {
+        (Reverse(self.nodes[node].weight), self.nodes[node].is_leaf())
+    }
+
+    fn block_leader_rank(&self, key: (Reverse<u64>, bool)) -> usize {
+        self.order
+            .partition_point(|&node| self.block_key(node) < key)
+    }
+
+    fn block_leader(&self, node: usize) -> usize {
+        self.order[self.block_leader_rank(self.block_key(node))]
+    }
+
+    fn slide_and_increment(&mut self, node: usize) -> Option<usize> {
+        let former_parent = self.nodes[node].parent;
+        let weight = self.nodes[node].weight;
+
+        if self.nodes[node].is_leaf() {
+            self.slide_leaf(node, weight);
+            self.nodes[node].weight += 1;
+            self.nodes[node].parent
+        } else {
+            self.slide_internal(node, weight);
+            self.nodes[node].weight += 1;
+            former_parent
+        }
+    }
+
+    /// Slides a leaf past the internal nodes of its weight one position at a time, since
+    /// those nodes carry whole subtrees that must keep their relative order.
+    fn slide_leaf(&mut self, node: usize, weight: u64) {
+        let ahead_key = (Reverse(weight), false);
+
+        while self.rank[node] > 0 {
+            let ahead = self.order[self.rank[node] - 1];
+            if self.block_key(ahead) != ahead_key {
+                break;
+            }
+            self.swap_nodes(node, ahead);
+        }
+    }
+
+    /// The leaves an internal node slides past all share one weight, so taking the slot of
+    /// their leader leaves the tree in the same shape as shifting each of them down by one.
+    fn slide_internal(&mut self, node: usize, weight: u64) {
+        let ahead_key = (Reverse(weight + 1), true);
+        let leader_rank = self.block_leader_rank(ahead_key);
+
+        if leader_rank < self.rank[node] && self.block_key(self.order[leader_rank]) == ahead_key {
+            self.swap_nodes(node, self.order[leader_rank]);
+        }
+    }
+
+    fn swap_nodes(&mut self, first: usize, second: usize) {
+        if first == second {
+            return;
+        }
+
+        let first_parent = self.nodes[first].parent;
+        let second_parent = self.nodes[second].parent;
+
+        if first_parent == second_parent {
+            if let Some(parent) = first_parent {
+                let parent = &mut self.nodes[parent];
+                std::mem::swap(&mut parent.left, &mut parent.right);
+            }
+        } else {
+            self.replace_child(first_parent, first, second);
+            self.replace_child(second_parent, second, first);
+            self.nodes[first].parent = second_parent;
+            self.nodes[second].parent = first_parent;
+        }
+
+        let (first_rank, second_rank) = (self.rank[first], self.rank[second]);
+        self.order.swap(first_rank, second_rank);
+        self.rank[first] = second_rank;
+        self.rank[second] = first_rank;
+    }
+
+    fn replace_child(&mut self, parent: Option<usize>, old_child: usize, new_child: usize) {
+        if let Some(parent) = parent {
+            let parent = &mut self.nodes[parent];
+            if parent.left == Some(old_child) {
+                parent.left = Some(new_child);
+            } else {
+                parent.right = Some(new_child);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::canonical;
+use crate::huffman::Huffman;
+use crate::tree::Symbol;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Information-theory figures for one input. Entropies are in bits per byte, while the
+/// Huffman figures are in bits per coded symbol.
+pub struct EntropyReport {
+    pub total_bytes: usize,
+    pub total_symbols: u64,
+    pub distinct_symbols: usize,
+    pub order0_entropy: f64,
+    pub order1_entropy: f64,
+    pub order2_entropy: f64,
+    pub symbol_entropy: f64,
+    pub average_code_length: f64,
+    pub redundancy: f64,
+    pub kraft_sum: f64,
+    pub entropy_bound_size: usize,
+    pub huffman_data_size: usize,
+}
+
+/// Returns `None` when `file_data` holds no complete symbol.
+pub fn analyze(
+    file_data: &[u8],
+    symbol: Symbol,
+    max_code_length: Option<u8>,
+) -> Option<EntropyReport> {
+    let mut huffman = Huffman::new(symbol).with_max_code_length(max_code_length);
+    huffman.count_frequencies(file_data);
+
+    let frequencies = huffman.frequencies();
+    let total_symbols: u64 = frequencies.iter().map(|&(_, count)| count as u64).sum();
+    if total_symbols == 0 {
+        return None;
+    }
+
+    let lengths = huffman.compute_code_lengths();
+    let coded_bits: u64 = frequencies
+        .iter()
+        .map(|(symbol, count)| *count as u64 * lengths.get(symbol).copied().unwrap_or(0) as u64)
+        .sum();
+
+    let symbol_entropy = conditional_entropy(frequencies.iter().map(|&(_, count)| ((), count)));
+    let average_code_length = coded_bits as f64 / total_symbols as f64;
+
+    Some(EntropyReport {
+        total_bytes: file_data.len(),
+        total_symbols,
+        distinct_symbols: frequencies.len(),
+        order0_entropy: order0_entropy(file_data),
+        order1_entropy: order1_entropy(file_data),
+        order2_entropy: order2_entropy(file_data),
+        symbol_entropy,
+        average_code_length,
+        redundancy: average_code_length - symbol_entropy,
+        kraft_sum: canonical::kraft_sum(&lengths),
+        entropy_bound_size: (symbol_entropy * total_symbols as f64 / 8.0).ceil() as usize,
+        huffman_data_size: coded_bits.div_ceil(8) as usize,
+    })
+}
+
+fn order0_entropy(file_data: &[u8]) -> f64 {
+    let mut huffman = Huffman::new(Symbol::OneByte(0));
+    huffman.count_frequencies(file_data);
+
+    conditional_entropy(
+        huffman
+            .frequencies()
+            .into_iter()
+            .map(|(_, count)| ((), count)),
+    )
+}
+
+/// Uses the same per-previous-byte counts as the order-1 context Huffman mode.
+fn order1_entropy(file_data: &[u8]) -> f64 {
+    let mut huffman = Huffman::new(Symbol::Context(0, 0));
+    huffman.count_frequencies(file_data);
+
+    conditional_entropy(huffman.frequencies().into_iter().map(|(symbol, count)| {
+        let previous = match symbol {
+            Symbol::Context(previous, _) => previous,
+            _ => 0,
+        };
+        (previous, count)
+    }))
+}
+
+/// Like the context mode, the bytes before the start of the input count as zeros.
+fn order2_entropy(file_data: &[u8]) -> f64 {
+    let mut counters: HashMap<(u8, u8, u8), u32> = HashMap::new();
+    let mut history = (0u8, 0u8);
+
+    for &byte in file_data {
+        *counters.entry((history.0, history.1, byte)).or_insert(0) += 1;
+        history = (history.1, byte);
+    }
+
+    conditional_entropy(
+        counters
+            .into_iter()
+            .map(|((second, first, _), count)| ((second, first), count)),
+    )
+}
+
+/// Empirical entropy of the symbols given their context, from `(context, count)` pairs with
+/// one pair per distinct symbol in that context.
+fn conditional_entropy<C, I>(counts: I) -> f64
+where
+    C: Hash + Eq,
+    I: IntoIterator<Item = (C, u32)>,
+{
+    let counts: Vec<(C, u32)> = counts.into_iter().filter(|&(_, count)| count > 0).collect();
+
+    let mut context_totals: HashMap<&C, u64> = HashMap::new();
+    for (context, count) in &counts {
+        *context_totals.entry(context).or_insert(0) += *count as u64;
+    }
+
+    let total: u64 = context_totals.values().sum();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let bits: f64 = counts
+        .iter()
+        .map(|(context, count)| {
+            let count = *count as f64;
+            count * (context_totals[context] as f64 / count).log2()
+        })
+        .sum();
+
+    bits / total as f64
+}
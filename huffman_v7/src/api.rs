@@ -1,7 +1,16 @@
-use crate::models::{DecodeResponse, DecodingOptions, EncodeResponse, ErrorResponse};
-use crate::tree::Symbol;
+use crate::analysis;
+use crate::arithmetic::ArithmeticCoder;
+use crate::error::HuffmanError;
+use crate::huffman::HeaderFormat;
+use crate::models::{
+    AnalysisOptions, AnalysisResponse, ArithmeticOptions, DecodeResponse, DecodingOptions,
+    EncodeResponse, ErrorResponse, HuffmanErrorResponse, TableListResponse, TrainTableOptions,
+    TrainTableResponse, TreeNode, TreeOptions, TreeResponse, TunstallOptions,
+};
+use crate::tables::{StaticTable, TableStore};
+use crate::tree::{Node, Symbol};
+use crate::tunstall::Tunstall;
 use crate::{huffman::Huffman, models::EncodingOptions};
-use actix_files;
 use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse, Result};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
@@ -42,16 +51,54 @@ pub async fn encode_file(
         }));
     }
 
-    let symbol = if use_two_bytes {
+    let symbol = if query.words {
+        Symbol::Word(0)
+    } else if query.code_points {
+        Symbol::CodePoint('\0')
+    } else if query.context {
+        Symbol::Context(0, 0)
+    } else if use_two_bytes {
         Symbol::TwoBytes(0)
     } else {
         Symbol::OneByte(0)
     };
 
-    let mut huffman = Huffman::new(symbol);
-    let compressed = huffman.compress(&file_data);
+    let header_format = if query.adaptive {
+        HeaderFormat::Adaptive
+    } else if query.parallel {
+        HeaderFormat::ParallelBlocks
+    } else if query.block_size.is_some() {
+        HeaderFormat::Blocks
+    } else if query.code_lengths {
+        HeaderFormat::CodeLengths
+    } else {
+        HeaderFormat::Frequencies
+    };
+
+    let mut huffman = Huffman::new(symbol)
+        .with_header_format(header_format)
+        .with_max_code_length(query.max_code_len)
+        .with_frequency_scaling(query.scale_frequencies)
+        .with_block_size(query.block_size)
+        .with_shannon_fano(query.shannon_fano);
+    if let Some(table_id) = &query.table {
+        match TableStore::default().load(table_id) {
+            Ok(table) => huffman = huffman.with_static_table(table),
+            Err(error) => return Ok(huffman_error_response(error)),
+        }
+    }
+    let (huffman, compressed, original_size) = web::block(move || {
+        let compressed = huffman.compress(&file_data);
+        (huffman, compressed, file_data.len())
+    })
+    .await?;
+
+    let compressed = match compressed {
+        Ok(compressed) => compressed,
+        Err(error) => return Ok(huffman_error_response(error)),
+    };
 
-    let stats = huffman.get_compression_stats(file_data.len(), compressed.len());
+    let stats = huffman.get_compression_stats(original_size, compressed.len());
     let codes = if show_codes {
         huffman.get_codes()
     } else {
@@ -62,9 +109,16 @@ pub async fn encode_file(
 
     let file_data_base64 = STANDARD.encode(&compressed);
 
+    let byte_fallback = huffman.used_byte_fallback();
+    let message = if byte_fallback {
+        "File encoded successfully; it is not valid UTF-8, so it was coded as bytes"
+    } else {
+        "File encoded successfully"
+    };
+
     Ok(HttpResponse::Ok().json(EncodeResponse {
         success: true,
-        message: "File encoded successfully".to_string(),
+        message: message.to_string(),
         filename: output_filename,
         original_size: stats.original_size,
         compressed_size: stats.compressed_size,
@@ -73,6 +127,7 @@ pub async fn encode_file(
         compression_ratio: stats.compression_ratio,
         space_saved: stats.space_saved,
         percentage_saved: stats.percentage_saved,
+        byte_fallback,
         codes,
         file_data: file_data_base64,
     }))
@@ -118,13 +173,23 @@ pub async fn decode_file(
         }));
     }
 
-    let mut huffman = Huffman::new(Symbol::OneByte(0));
-    let decompressed = huffman.decompress(&file_data);
+    let mut huffman = Huffman::new(Symbol::OneByte(0)).with_table_store(TableStore::default());
+    let original_size = file_data.len();
+    let (huffman, decompressed) = web::block(move || {
+        let decompressed = huffman.decompress(&file_data);
+        (huffman, decompressed)
+    })
+    .await?;
 
-    let codes = if show_codes {
-        huffman.get_codes()
+    let decompressed = match decompressed {
+        Ok(decompressed) => decompressed,
+        Err(error) => return Ok(huffman_error_response(error)),
+    };
+
+    let (codes, frequencies) = if show_codes {
+        (huffman.get_codes(), huffman.get_frequencies())
     } else {
-        None
+        (None, None)
     };
 
     let timestamp = Local::now().format("%d-%m-%Y-%H-%M").to_string();
@@ -148,13 +213,576 @@ pub async fn decode_file(
         success: true,
         message: "File decoded successfully".to_string(),
         filename: output_filename,
-        original_size: file_data.len(),
+        original_size,
         decompressed_size: decompressed.len(),
         codes,
+        frequencies,
         file_data: file_data_base64,
     }))
 }
 
+pub async fn arithmetic_encode(
+    mut payload: Multipart,
+    query: web::Query<ArithmeticOptions>,
+) -> Result<HttpResponse> {
+    let mut file_data = Vec::new();
+    let mut filename = String::from("unknown");
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let content_disposition = field.content_disposition();
+        if let Some(name) = content_disposition.get_name() {
+            if name == "file" {
+                if let Some(fname) = content_disposition.get_filename() {
+                    filename = fname.to_string();
+                }
+
+                while let Some(chunk) = field.next().await {
+                    let data = chunk?;
+                    file_data.extend_from_slice(&data);
+                }
+            }
+        }
+    }
+
+    if file_data.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "No file data received".to_string(),
+        }));
+    }
+
+    let symbol = if query.words {
+        Symbol::Word(0)
+    } else if query.code_points {
+        Symbol::CodePoint('\0')
+    } else if query.context {
+        Symbol::Context(0, 0)
+    } else if query.two_bytes {
+        Symbol::TwoBytes(0)
+    } else {
+        Symbol::OneByte(0)
+    };
+
+    let mut coder = ArithmeticCoder::new(symbol).with_adaptive(query.adaptive);
+    let (coder, compressed, original_size) = web::block(move || {
+        let compressed = coder.compress(&file_data);
+        (coder, compressed, file_data.len())
+    })
+    .await?;
+
+    let compressed = match compressed {
+        Ok(compressed) => compressed,
+        Err(error) => return Ok(huffman_error_response(error)),
+    };
+
+    let stats = coder.get_compression_stats(original_size, compressed.len());
+
+    Ok(HttpResponse::Ok().json(EncodeResponse {
+        success: true,
+        message: "File encoded successfully".to_string(),
+        filename: format!("{}.hac", filename),
+        original_size: stats.original_size,
+        compressed_size: stats.compressed_size,
+        header_size: stats.header_size,
+        compressed_data_size: stats.compressed_data_size,
+        compression_ratio: stats.compression_ratio,
+        space_saved: stats.space_saved,
+        percentage_saved: stats.percentage_saved,
+        byte_fallback: false,
+        codes: None,
+        file_data: STANDARD.encode(&compressed),
+    }))
+}
+
+pub async fn arithmetic_decode(mut payload: Multipart) -> Result<HttpResponse> {
+    let mut file_data = Vec::new();
+    let mut filename = String::from("unknown.hac");
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let content_disposition = field.content_disposition();
+        if let Some(name) = content_disposition.get_name() {
+            if name == "file" {
+                if let Some(fname) = content_disposition.get_filename() {
+                    filename = fname.to_string();
+                }
+
+                while let Some(chunk) = field.next().await {
+                    let data = chunk?;
+                    file_data.extend_from_slice(&data);
+                }
+            }
+        }
+    }
+
+    if file_data.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "No file data received".to_string(),
+        }));
+    }
+
+    if !filename.ends_with(".hac") && !filename.ends_with(".HAC") {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "Invalid file extension. Only .hac files are supported.".to_string(),
+        }));
+    }
+
+    let original_size = file_data.len();
+    let decompressed =
+        web::block(move || ArithmeticCoder::new(Symbol::OneByte(0)).decompress(&file_data)).await?;
+
+    let decompressed = match decompressed {
+        Ok(decompressed) => decompressed,
+        Err(error) => return Ok(huffman_error_response(error)),
+    };
+
+    let timestamp = Local::now().format("%d-%m-%Y-%H-%M").to_string();
+
+    let base_name = filename
+        .strip_suffix(".hac")
+        .or_else(|| filename.strip_suffix(".HAC"))
+        .unwrap_or(&filename);
+
+    let output_filename = if let Some(dot_pos) = base_name.rfind('.') {
+        let name_part = &base_name[..dot_pos];
+        let ext_part = &base_name[dot_pos..];
+        format!("{}.{}{}", name_part, timestamp, ext_part)
+    } else {
+        format!("{}.{}", base_name, timestamp)
+    };
+
+    Ok(HttpResponse::Ok().json(DecodeResponse {
+        success: true,
+        message: "File decoded successfully".to_string(),
+        filename: output_filename,
+        original_size,
+        decompressed_size: decompressed.len(),
+        codes: None,
+        frequencies: None,
+        file_data: STANDARD.encode(&decompressed),
+    }))
+}
+
+pub async fn tunstall_encode(
+    mut payload: Multipart,
+    query: web::Query<TunstallOptions>,
+) -> Result<HttpResponse> {
+    let mut file_data = Vec::new();
+    let mut filename = String::from("unknown");
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let content_disposition = field.content_disposition();
+        if let Some(name) = content_disposition.get_name() {
+            if name == "file" {
+                if let Some(fname) = content_disposition.get_filename() {
+                    filename = fname.to_string();
+                }
+
+                while let Some(chunk) = field.next().await {
+                    let data = chunk?;
+                    file_data.extend_from_slice(&data);
+                }
+            }
+        }
+    }
+
+    if file_data.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "No file data received".to_string(),
+        }));
+    }
+
+    let symbol = if query.words {
+        Symbol::Word(0)
+    } else if query.code_points {
+        Symbol::CodePoint('\0')
+    } else if query.context {
+        Symbol::Context(0, 0)
+    } else if query.two_bytes {
+        Symbol::TwoBytes(0)
+    } else {
+        Symbol::OneByte(0)
+    };
+
+    let mut tunstall = Tunstall::new(symbol).with_code_bits(query.code_bits);
+    let (tunstall, compressed, original_size) = web::block(move || {
+        let compressed = tunstall.compress(&file_data);
+        (tunstall, compressed, file_data.len())
+    })
+    .await?;
+
+    let compressed = match compressed {
+        Ok(compressed) => compressed,
+        Err(error) => return Ok(huffman_error_response(error)),
+    };
+
+    let stats = tunstall.get_compression_stats(original_size, compressed.len());
+    let codes = if query.show_codes {
+        tunstall.get_codes()
+    } else {
+        None
+    };
+
+    Ok(HttpResponse::Ok().json(EncodeResponse {
+        success: true,
+        message: "File encoded successfully".to_string(),
+        filename: format!("{}.htu", filename),
+        original_size: stats.original_size,
+        compressed_size: stats.compressed_size,
+        header_size: stats.header_size,
+        compressed_data_size: stats.compressed_data_size,
+        compression_ratio: stats.compression_ratio,
+        space_saved: stats.space_saved,
+        percentage_saved: stats.percentage_saved,
+        byte_fallback: false,
+        codes,
+        file_data: STANDARD.encode(&compressed),
+    }))
+}
+
+pub async fn tunstall_decode(
+    mut payload: Multipart,
+    query: web::Query<DecodingOptions>,
+) -> Result<HttpResponse> {
+    let mut file_data = Vec::new();
+    let mut filename = String::from("unknown.htu");
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let content_disposition = field.content_disposition();
+        if let Some(name) = content_disposition.get_name() {
+            if name == "file" {
+                if let Some(fname) = content_disposition.get_filename() {
+                    filename = fname.to_string();
+                }
+
+                while let Some(chunk) = field.next().await {
+                    let data = chunk?;
+                    file_data.extend_from_slice(&data);
+                }
+            }
+        }
+    }
+
+    if file_data.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "No file data received".to_string(),
+        }));
+    }
+
+    if !filename.ends_with(".htu") && !filename.ends_with(".HTU") {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "Invalid file extension. Only .htu files are supported.".to_string(),
+        }));
+    }
+
+    let mut tunstall = Tunstall::new(Symbol::OneByte(0));
+    let original_size = file_data.len();
+    let (tunstall, decompressed) = web::block(move || {
+        let decompressed = tunstall.decompress(&file_data);
+        (tunstall, decompressed)
+    })
+    .await?;
+
+    let decompressed = match decompressed {
+        Ok(decompressed) => decompressed,
+        Err(error) => return Ok(huffman_error_response(error)),
+    };
+
+    let codes = if query.show_codes {
+        tunstall.get_codes()
+    } else {
+        None
+    };
+
+    let timestamp = Local::now().format("%d-%m-%Y-%H-%M").to_string();
+
+    let base_name = filename
+        .strip_suffix(".htu")
+        .or_else(|| filename.strip_suffix(".HTU"))
+        .unwrap_or(&filename);
+
+    let output_filename = if let Some(dot_pos) = base_name.rfind('.') {
+        let name_part = &base_name[..dot_pos];
+        let ext_part = &base_name[dot_pos..];
+        format!("{}.{}{}", name_part, timestamp, ext_part)
+    } else {
+        format!("{}.{}", base_name, timestamp)
+    };
+
+    Ok(HttpResponse::Ok().json(DecodeResponse {
+        success: true,
+        message: "File decoded successfully".to_string(),
+        filename: output_filename,
+        original_size,
+        decompressed_size: decompressed.len(),
+        codes,
+        frequencies: None,
+        file_data: STANDARD.encode(&decompressed),
+    }))
+}
+
+/// Bad uploads are the client's fault, so they map to 4xx with a machine-readable `kind`.
+fn huffman_error_response(error: HuffmanError) -> HttpResponse {
+    let mut response = match error {
+        HuffmanError::InputTooLarge => HttpResponse::PayloadTooLarge(),
+        HuffmanError::UnknownTable => HttpResponse::NotFound(),
+        _ => HttpResponse::BadRequest(),
+    };
+
+    response.json(HuffmanErrorResponse {
+        success: false,
+        error: error.to_string(),
+        kind: error.kind().to_string(),
+    })
+}
+
+/// Every uploaded `file` field is one sample of the training corpus.
+pub async fn train_table(
+    mut payload: Multipart,
+    query: web::Query<TrainTableOptions>,
+) -> Result<HttpResponse> {
+    if !TableStore::is_valid_id(&query.name) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "Table names may only hold up to 64 letters, digits, '-' and '_'".to_string(),
+        }));
+    }
+
+    let mut samples = Vec::new();
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let content_disposition = field.content_disposition();
+        if let Some(name) = content_disposition.get_name() {
+            if name == "file" {
+                let mut sample = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    let data = chunk?;
+                    sample.extend_from_slice(&data);
+                }
+                samples.push(sample);
+            }
+        }
+    }
+
+    let corpus_size: usize = samples.iter().map(Vec::len).sum();
+    if corpus_size == 0 {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            success: false,
+            error: "No file data received".to_string(),
+        }));
+    }
+    if corpus_size > u32::MAX as usize {
+        return Ok(huffman_error_response(HuffmanError::InputTooLarge));
+    }
+
+    let symbol = if query.context {
+        Symbol::Context(0, 0)
+    } else if query.two_bytes {
+        Symbol::TwoBytes(0)
+    } else {
+        Symbol::OneByte(0)
+    };
+
+    let table_id = query.name.clone();
+    let max_code_length = query.max_code_len;
+    let trained = web::block(move || {
+        StaticTable::train(table_id, symbol, max_code_length, &samples).map(|table| {
+            let saved = TableStore::default().save(&table);
+            (table, saved)
+        })
+    })
+    .await?;
+
+    let (table, saved) = match trained {
+        Ok(trained) => trained,
+        Err(error) => return Ok(huffman_error_response(error)),
+    };
+
+    let table_size = match saved {
+        Ok(table_size) => table_size,
+        Err(error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                success: false,
+                error: format!("Could not store the table: {}", error),
+            }));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(TrainTableResponse {
+        success: true,
+        table_id: table.id.clone(),
+        corpus_size,
+        symbol_count: table.symbol_count(),
+        table_size,
+    }))
+}
+
+pub async fn list_tables() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(TableListResponse {
+        success: true,
+        tables: TableStore::default().list(),
+    }))
+}
+
+pub async fn huffman_tree(
+    mut payload: Multipart,
+    query: web::Query<TreeOptions>,
+) -> Result<HttpResponse> {
+    let mut file_data = Vec::new();
+    let mut filename = String::from("unknown");
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let content_disposition = field.content_disposition();
+        if let Some(name) = content_disposition.get_name() {
+            if name == "file" {
+                if let Some(fname) = content_disposition.get_filename() {
+                    filename = fname.to_string();
+                }
+
+                while let Some(chunk) = field.next().await {
+                    let data = chunk?;
+                    file_data.extend_from_slice(&data);
+                }
+            }
+        }
+    }
+
+    let symbol = if query.two_bytes {
+        Symbol::TwoBytes(0)
+    } else {
+        Symbol::OneByte(0)
+    };
+
+    let tree = match Huffman::new(symbol).frequency_tree(&file_data) {
+        Some(tree) => tree,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                success: false,
+                error: "No symbols to build a tree from".to_string(),
+            }));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(TreeResponse {
+        success: true,
+        filename,
+        tree: tree_node(&tree, String::new()),
+        dot: tree.to_dot(),
+    }))
+}
+
+pub async fn entropy_analysis(
+    mut payload: Multipart,
+    query: web::Query<AnalysisOptions>,
+) -> Result<HttpResponse> {
+    let mut file_data = Vec::new();
+    let mut filename = String::from("unknown");
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let content_disposition = field.content_disposition();
+        if let Some(name) = content_disposition.get_name() {
+            if name == "file" {
+                if let Some(fname) = content_disposition.get_filename() {
+                    filename = fname.to_string();
+                }
+
+                while let Some(chunk) = field.next().await {
+                    let data = chunk?;
+                    file_data.extend_from_slice(&data);
+                }
+            }
+        }
+    }
+
+    let symbol = if query.code_points {
+        Symbol::CodePoint('\0')
+    } else if query.two_bytes {
+        Symbol::TwoBytes(0)
+    } else {
+        Symbol::OneByte(0)
+    };
+
+    let max_code_length = query.max_code_len;
+    let report = web::block(move || analysis::analyze(&file_data, symbol, max_code_length)).await?;
+
+    let report = match report {
+        Some(report) => report,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                success: false,
+                error: "No symbols to analyze".to_string(),
+            }));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(AnalysisResponse {
+        success: true,
+        filename,
+        total_bytes: report.total_bytes,
+        total_symbols: report.total_symbols,
+        distinct_symbols: report.distinct_symbols,
+        order0_entropy: report.order0_entropy,
+        order1_entropy: report.order1_entropy,
+        order2_entropy: report.order2_entropy,
+        symbol_entropy: report.symbol_entropy,
+        average_code_length: report.average_code_length,
+        redundancy: report.redundancy,
+        kraft_sum: report.kraft_sum,
+        entropy_bound_size: report.entropy_bound_size,
+        huffman_data_size: report.huffman_data_size,
+    }))
+}
+
+/// Leaves carry their path from the root as `code`, which the tree from `frequency_tree`
+/// lays out as the canonical code; a lone leaf still gets one bit.
+fn tree_node(node: &Node, path: String) -> TreeNode {
+    if node.is_leaf() {
+        return TreeNode {
+            frequency: node.frequency,
+            symbol: Some(node.symbol.to_string()),
+            code: Some(if path.is_empty() {
+                "0".to_string()
+            } else {
+                path
+            }),
+            left: None,
+            right: None,
+        };
+    }
+
+    TreeNode {
+        frequency: node.frequency,
+        symbol: None,
+        code: None,
+        left: node
+            .left
+            .as_ref()
+            .map(|left| Box::new(tree_node(left, format!("{}0", path)))),
+        right: node
+            .right
+            .as_ref()
+            .map(|right| Box::new(tree_node(right, format!("{}1", path)))),
+    }
+}
+
 pub async fn index() -> Result<actix_files::NamedFile> {
     Ok(actix_files::NamedFile::open("static/index.html")?)
 }
@@ -0,0 +1,511 @@
+use crate::bit_operations::{BitReader, BitWriter};
+use crate::error::HuffmanError;
+use crate::huffman::{
+    read_gap_list, read_header_bit, read_header_bits, write_gap_list, CompressionStats, Huffman,
+};
+use crate::tree::Symbol;
+
+const HAC_MAGIC: &[u8; 3] = b"HAC";
+const HAC_VERSION: u8 = 1;
+const CODE_BITS: u32 = 32;
+const WHOLE: u64 = 1 << CODE_BITS;
+const HALF: u64 = WHOLE / 2;
+const QUARTER: u64 = WHOLE / 4;
+const STATIC_TOTAL_LIMIT: u64 = 1 << 24;
+const ADAPTIVE_TOTAL_LIMIT: u64 = 1 << 24;
+const ADAPTIVE_INCREMENT: u64 = 32;
+const FREQUENCY_WIDTH_FIELD_BITS: u32 = 5;
+const SYMBOL_COUNT_FIELD_BITS: u32 = 17;
+
+/// Cumulative symbol counts kept in a Fenwick tree, so both the interval lookup and the
+/// adaptive update are logarithmic in the alphabet size.
+struct FrequencyModel {
+    tree: Vec<u64>,
+    total: u64,
+}
+
+impl FrequencyModel {
+    fn new(size: usize) -> Self {
+        Self {
+            tree: vec![0; size + 1],
+            total: 0,
+        }
+    }
+
+    fn uniform(size: usize) -> Self {
+        let mut model = Self::new(size);
+        for index in 0..size {
+            model.add(index, 1);
+        }
+        model
+    }
+
+    fn size(&self) -> usize {
+        self.tree.len() - 1
+    }
+
+    fn add(&mut self, index: usize, amount: u64) {
+        self.total += amount;
+
+        let mut position = index + 1;
+        while position < self.tree.len() {
+            self.tree[position] += amount;
+            position += position & position.wrapping_neg();
+        }
+    }
+
+    /// Sum of the counts of every symbol below `index`.
+    fn cumulative(&self, index: usize) -> u64 {
+        let mut sum = 0;
+        let mut position = index;
+        while position > 0 {
+            sum += self.tree[position];
+            position -= position & position.wrapping_neg();
+        }
+        sum
+    }
+
+    fn interval(&self, index: usize) -> (u64, u64) {
+        (self.cumulative(index), self.cumulative(index + 1))
+    }
+
+    /// Finds the symbol whose interval holds `target`, skipping symbols with a zero count.
+    fn find(&self, target: u64) -> usize {
+        let mut position = 0;
+        let mut remaining = target;
+        let mut step = self.size().next_power_of_two();
+
+        while step > 0 {
+            let next = position + step;
+            if next <= self.size() && self.tree[next] <= remaining {
+                position = next;
+                remaining -= self.tree[next];
+            }
+            step >>= 1;
+        }
+
+        position
+    }
+
+    /// Halves every count, keeping seen symbols at one or more so they stay codable.
+    fn halve(&mut self) {
+        let counts: Vec<u64> = (0..self.size())
+            .map(|index| {
+                let (low, high) = self.interval(index);
+                high - low
+            })
+            .collect();
+
+        *self = Self::new(counts.len());
+        for (index, count) in counts.into_iter().enumerate() {
+            self.add(index, count.div_ceil(2));
+        }
+    }
+}
+
+/// Bit-oriented arithmetic encoder in the style of Witten, Neal and Cleary: the interval is
+/// renormalised one bit at a time, and straddles of the midpoint are deferred as pending bits.
+struct ArithmeticEncoder {
+    low: u64,
+    high: u64,
+    pending: u32,
+}
+
+impl ArithmeticEncoder {
+    fn new() -> Self {
+        Self {
+            low: 0,
+            high: WHOLE - 1,
+            pending: 0,
+        }
+    }
+
+    fn encode(&mut self, writer: &mut BitWriter, (low, high): (u64, u64), total: u64) {
+        let range = self.high - self.low + 1;
+        self.high = self.low + range * high / total - 1;
+        self.low += range * low / total;
+
+        loop {
+            if self.high < HALF {
+                self.emit(writer, false);
+            } else if self.low >= HALF {
+                self.emit(writer, true);
+                self.low -= HALF;
+                self.high -= HALF;
+            } else if self.low >= QUARTER && self.high < HALF + QUARTER {
+                self.pending += 1;
+                self.low -= QUARTER;
+                self.high -= QUARTER;
+            } else {
+                break;
+            }
+
+            self.low <<= 1;
+            self.high = (self.high << 1) | 1;
+        }
+    }
+
+    fn emit(&mut self, writer: &mut BitWriter, bit: bool) {
+        writer.write_bit(bit);
+        for _ in 0..self.pending {
+            writer.write_bit(!bit);
+        }
+        self.pending = 0;
+    }
+
+    /// Writes just enough bits to pin a value inside the final interval.
+    fn finish(mut self, writer: &mut BitWriter) {
+        self.pending += 1;
+        let bit = self.low >= QUARTER;
+        self.emit(writer, bit);
+    }
+}
+
+struct ArithmeticDecoder {
+    low: u64,
+    high: u64,
+    value: u64,
+    padding: u32,
+}
+
+impl ArithmeticDecoder {
+    fn new(reader: &mut BitReader) -> Self {
+        let mut decoder = Self {
+            low: 0,
+            high: WHOLE - 1,
+            value: 0,
+            padding: 0,
+        };
+
+        for _ in 0..CODE_BITS {
+            decoder.value = (decoder.value << 1) | decoder.next_bit(reader) as u64;
+        }
+        decoder
+    }
+
+    /// The encoder stops once the final interval is pinned, so the decoder reads zeros past
+    /// the end; needing more than a full window of them means the data was cut short.
+    fn next_bit(&mut self, reader: &mut BitReader) -> bool {
+        reader.read_bit().unwrap_or_else(|| {
+            self.padding += 1;
+            false
+        })
+    }
+
+    fn target(&self, total: u64) -> Result<u64, HuffmanError> {
+        if self.padding > CODE_BITS {
+            return Err(HuffmanError::PrematureEnd);
+        }
+        if self.value < self.low || self.value > self.high {
+            return Err(HuffmanError::InvalidCode);
+        }
+
+        let range = self.high - self.low + 1;
+        Ok(((self.value - self.low + 1) * total - 1) / range)
+    }
+
+    fn consume(&mut self, reader: &mut BitReader, (low, high): (u64, u64), total: u64) {
+        let range = self.high - self.low + 1;
+        self.high = self.low + range * high / total - 1;
+        self.low += range * low / total;
+
+        loop {
+            // Mirrors the encoder's renormalisation without emitting anything.
+            let offset = if self.high < HALF {
+                0
+            } else if self.low >= HALF {
+                HALF
+            } else if self.low >= QUARTER && self.high < HALF + QUARTER {
+                QUARTER
+            } else {
+                break;
+            };
+
+            self.low -= offset;
+            self.high -= offset;
+            self.value -= offset;
+
+            self.low <<= 1;
+            self.high = (self.high << 1) | 1;
+            self.value = (self.value << 1) | self.next_bit(reader) as u64;
+        }
+    }
+}
+
+/// Static and adaptive arithmetic coding over the same one- and two-byte symbols as
+/// [`Huffman`], stored in its own `.hac` container.
+pub struct ArithmeticCoder {
+    symbol: Symbol,
+    adaptive: bool,
+    trailing_byte: Option<u8>,
+    header_size: usize,
+}
+
+impl ArithmeticCoder {
+    /// The coder holds a single frequency model over bytes or byte pairs, so `compress`
+    /// rejects context, word and code point modes, which would each need a model of their own.
+    pub fn new(symbol: Symbol) -> Self {
+        Self {
+            symbol,
+            adaptive: false,
+            trailing_byte: None,
+            header_size: 0,
+        }
+    }
+
+    pub fn with_adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    fn symbol_bits(&self) -> u32 {
+        match self.symbol {
+            Symbol::TwoBytes(_) => 16,
+            _ => 8,
+        }
+    }
+
+    fn alphabet_size(&self) -> usize {
+        1 << self.symbol_bits()
+    }
+
+    fn symbol_index(symbol: Symbol) -> usize {
+        match symbol {
+            Symbol::OneByte(byte) => byte as usize,
+            Symbol::TwoBytes(value) => value as usize,
+            Symbol::Context(..) | Symbol::Word(_) | Symbol::CodePoint(_) => unreachable!(),
+        }
+    }
+
+    pub fn compress(&mut self, file_data: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+        if file_data.len() > u32::MAX as usize {
+            return Err(HuffmanError::InputTooLarge);
+        }
+
+        if !matches!(self.symbol, Symbol::OneByte(_) | Symbol::TwoBytes(_)) {
+            return Err(HuffmanError::IncompatibleOptions);
+        }
+
+        let mut writer = BitWriter::new();
+        let symbol_size = self.symbol_bits() as usize / 8;
+
+        self.trailing_byte = match self.symbol {
+            Symbol::TwoBytes(_) if file_data.len() % 2 == 1 => file_data.last().copied(),
+            _ => None,
+        };
+
+        self.write_container_header(&mut writer);
+        writer.write_n_bits((file_data.len() / symbol_size) as u32, 32);
+
+        let mut model = if self.adaptive {
+            FrequencyModel::uniform(self.alphabet_size())
+        } else {
+            let model = self.static_model(file_data);
+            self.write_frequency_table(&mut writer, &model);
+            model
+        };
+        self.header_size = writer.bit_len().div_ceil(8);
+
+        let mut encoder = ArithmeticEncoder::new();
+        let mut reader = BitReader::new(file_data);
+        while let Some(value) = reader.read_n_bits(self.symbol_bits()) {
+            let index = value as usize;
+            encoder.encode(&mut writer, model.interval(index), model.total);
+
+            if self.adaptive {
+                self.update(&mut model, index);
+            }
+        }
+        encoder.finish(&mut writer);
+
+        Ok(writer.finish())
+    }
+
+    fn update(&self, model: &mut FrequencyModel, index: usize) {
+        model.add(index, ADAPTIVE_INCREMENT);
+        if model.total > ADAPTIVE_TOTAL_LIMIT {
+            model.halve();
+        }
+    }
+
+    /// Counts come from [`Huffman::count_frequencies`] and are scaled down when their sum
+    /// would leave too little precision in the coding interval.
+    fn static_model(&self, file_data: &[u8]) -> FrequencyModel {
+        let mut huffman = Huffman::new(self.symbol);
+        huffman.count_frequencies(file_data);
+
+        let frequencies = huffman.frequencies();
+        let total: u64 = frequencies.iter().map(|&(_, count)| count as u64).sum();
+
+        let mut model = FrequencyModel::new(self.alphabet_size());
+        for (symbol, count) in frequencies {
+            let count = if total > STATIC_TOTAL_LIMIT {
+                (count as u64 * STATIC_TOTAL_LIMIT / total).max(1)
+            } else {
+                count as u64
+            };
+            model.add(Self::symbol_index(symbol), count);
+        }
+        model
+    }
+
+    fn write_container_header(&self, writer: &mut BitWriter) {
+        for &byte in HAC_MAGIC {
+            writer.write_n_bits(byte as u32, 8);
+        }
+        writer.write_n_bits(HAC_VERSION as u32, 8);
+
+        let symbol_mode = match self.symbol {
+            Symbol::TwoBytes(_) => 1,
+            _ => 0,
+        };
+        writer.write_n_bits(symbol_mode, 4);
+        writer.write_n_bits(self.adaptive as u32, 4);
+
+        if let Symbol::TwoBytes(_) = self.symbol {
+            writer.write_bit(self.trailing_byte.is_some());
+            if let Some(byte) = self.trailing_byte {
+                writer.write_n_bits(byte as u32, 8);
+            }
+        }
+    }
+
+    /// Lists the present symbols like the Huffman headers do, then every count in a shared
+    /// fixed width.
+    fn write_frequency_table(&self, writer: &mut BitWriter, model: &FrequencyModel) {
+        let present: Vec<(u32, u64)> = (0..model.size())
+            .map(|index| {
+                let (low, high) = model.interval(index);
+                (index as u32, high - low)
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect();
+
+        match self.symbol {
+            Symbol::TwoBytes(_) => {
+                let values: Vec<u32> = present.iter().map(|&(index, _)| index).collect();
+                write_gap_list(writer, &values, SYMBOL_COUNT_FIELD_BITS);
+            }
+            _ => {
+                for index in 0..model.size() {
+                    let (low, high) = model.interval(index);
+                    writer.write_bit(high > low);
+                }
+            }
+        }
+
+        let max_count = present.iter().map(|&(_, count)| count).max().unwrap_or(0);
+        let width = 64 - max_count.leading_zeros();
+        writer.write_n_bits(width, FREQUENCY_WIDTH_FIELD_BITS);
+        for &(_, count) in &present {
+            writer.write_n_bits(count as u32, width);
+        }
+    }
+
+    fn read_frequency_table(&self, reader: &mut BitReader) -> Result<FrequencyModel, HuffmanError> {
+        let present: Vec<usize> = match self.symbol {
+            Symbol::TwoBytes(_) => read_gap_list(reader, SYMBOL_COUNT_FIELD_BITS, u16::MAX as u32)?
+                .into_iter()
+                .map(|value| value as usize)
+                .collect(),
+            _ => {
+                let mut present = Vec::new();
+                for index in 0..self.alphabet_size() {
+                    if read_header_bit(reader)? {
+                        present.push(index);
+                    }
+                }
+                present
+            }
+        };
+
+        let width = read_header_bits(reader, FREQUENCY_WIDTH_FIELD_BITS)?;
+        let mut model = FrequencyModel::new(self.alphabet_size());
+        for index in present {
+            let count = read_header_bits(reader, width)? as u64;
+            if count == 0 {
+                return Err(HuffmanError::InvalidHeader);
+            }
+            model.add(index, count);
+        }
+
+        // Every symbol needs a non-empty slice of the narrowest interval the coder keeps.
+        if model.total > QUARTER {
+            return Err(HuffmanError::InvalidHeader);
+        }
+
+        Ok(model)
+    }
+
+    pub fn decompress(&mut self, file_data: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+        let is_container = file_data.len() > HAC_MAGIC.len()
+            && file_data.starts_with(HAC_MAGIC)
+            && file_data[HAC_MAGIC.len()] == HAC_VERSION;
+        if !is_container {
+            return Err(HuffmanError::InvalidHeader);
+        }
+
+        let mut reader = BitReader::new(&file_data[HAC_MAGIC.len() + 1..]);
+        let mut writer = BitWriter::new();
+
+        self.read_container_header(&mut reader)?;
+        let total_symbols = read_header_bits(&mut reader, 32)?;
+
+        let mut model = if self.adaptive {
+            FrequencyModel::uniform(self.alphabet_size())
+        } else {
+            self.read_frequency_table(&mut reader)?
+        };
+        if total_symbols > 0 && model.total == 0 {
+            return Err(HuffmanError::InvalidHeader);
+        }
+
+        let mut decoder = ArithmeticDecoder::new(&mut reader);
+        for _ in 0..total_symbols {
+            let index = model.find(decoder.target(model.total)?);
+
+            writer.write_n_bits(index as u32, self.symbol_bits());
+            decoder.consume(&mut reader, model.interval(index), model.total);
+
+            if self.adaptive {
+                self.update(&mut model, index);
+            }
+        }
+
+        if let Some(byte) = self.trailing_byte {
+            writer.write_n_bits(byte as u32, 8);
+        }
+
+        Ok(writer.finish())
+    }
+
+    fn read_container_header(&mut self, reader: &mut BitReader) -> Result<(), HuffmanError> {
+        self.symbol = match read_header_bits(reader, 4)? {
+            0 => Symbol::OneByte(0),
+            1 => Symbol::TwoBytes(0),
+            _ => return Err(HuffmanError::InvalidHeader),
+        };
+        self.adaptive = match read_header_bits(reader, 4)? {
+            0 => false,
+            1 => true,
+            _ => return Err(HuffmanError::InvalidHeader),
+        };
+
+        self.trailing_byte = match self.symbol {
+            Symbol::TwoBytes(_) if read_header_bit(reader)? => {
+                Some(read_header_bits(reader, 8)? as u8)
+            }
+            _ => None,
+        };
+
+        Ok(())
+    }
+
+    pub fn get_compression_stats(
+        &self,
+        original_size: usize,
+        compressed_size: usize,
+    ) -> CompressionStats {
+        CompressionStats::new(original_size, compressed_size, self.header_size)
+    }
+}
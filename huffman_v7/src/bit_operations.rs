@@ -38,6 +38,58 @@ impl<'a> BitReader<'a> {
         }
         Some(result)
     }
+
+    pub fn read_elias_gamma(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while !self.read_bit()? {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+
+        let rest = self.read_n_bits(zeros)?;
+        Some((1 << zeros) | rest)
+    }
+
+    /// Returns the next `count` bits (at most 24) without consuming them, padding with zeros past the end.
+    pub fn peek_n_bits(&self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+
+        let mut window = 0u32;
+        for offset in 0..4 {
+            let byte = self
+                .data
+                .get(self.byte_position + offset)
+                .copied()
+                .unwrap_or(0);
+            window = (window << 8) | byte as u32;
+        }
+
+        (window << self.bit_position) >> (32 - count)
+    }
+
+    pub fn skip_bits(&mut self, count: u32) {
+        let total_bits = self.bit_position as usize + count as usize;
+        self.byte_position += total_bits / 8;
+        self.bit_position = (total_bits % 8) as u8;
+    }
+
+    pub fn bits_remaining(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.byte_position * 8 + self.bit_position as usize)
+    }
+
+    /// Skips the rest of a partially read byte and returns the offset of the next whole byte.
+    pub fn align_to_byte(&mut self) -> usize {
+        if self.bit_position > 0 {
+            self.bit_position = 0;
+            self.byte_position += 1;
+        }
+
+        self.byte_position
+    }
 }
 
 pub struct BitWriter {
@@ -69,12 +121,42 @@ impl BitWriter {
     }
 
     pub fn write_n_bits(&mut self, value: u32, count: u32) {
+        if self.bit_position == 0 && count.is_multiple_of(8) {
+            for i in (0..count / 8).rev() {
+                self.data.push((value >> (i * 8)) as u8);
+            }
+            return;
+        }
+
         for i in (0..count).rev() {
             let bit = (value >> i) & 1 == 1;
             self.write_bit(bit);
         }
     }
 
+    /// Writes `value` (which must be at least 1) as its bit length minus one in zeros,
+    /// followed by the value itself.
+    pub fn write_elias_gamma(&mut self, value: u32) {
+        let length = 32 - value.leading_zeros();
+        self.write_n_bits(0, length - 1);
+        self.write_n_bits(value, length);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.bit_position == 0 {
+            self.data.extend_from_slice(bytes);
+            return;
+        }
+
+        for &byte in bytes {
+            self.write_n_bits(byte as u32, 8);
+        }
+    }
+
+    pub fn bit_len(&self) -> usize {
+        self.data.len() * 8 + self.bit_position as usize
+    }
+
     pub fn flush(&mut self) {
         if self.bit_position > 0 {
             self.data.push(self.current_byte);
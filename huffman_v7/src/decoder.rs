@@ -0,0 +1,114 @@
+use crate::bit_operations::BitReader;
+use crate::error::HuffmanError;
+use crate::tree::Symbol;
+use std::collections::{BTreeMap, HashMap};
+
+const LEVEL_BITS: u32 = 11;
+
+#[derive(Clone, Copy)]
+enum TableEntry {
+    Empty,
+    Short(Symbol, u8),
+    Next(usize),
+}
+
+/// One lookup table, indexed by the next `bits` bits of the stream.
+struct Level {
+    bits: u32,
+    entries: Vec<TableEntry>,
+}
+
+/// Codes up to `LEVEL_BITS` long resolve in one lookup. Longer codes point from their first
+/// bits to a further level indexed by the bits that follow, so every code, canonical or not,
+/// costs one lookup per `LEVEL_BITS` bits.
+pub struct TableDecoder {
+    levels: Vec<Level>,
+}
+
+impl TableDecoder {
+    pub fn new(codes: &HashMap<Symbol, Vec<bool>>) -> Self {
+        let codes: Vec<(&[bool], Symbol)> = codes
+            .iter()
+            .filter(|(_, code)| !code.is_empty())
+            .map(|(&symbol, code)| (code.as_slice(), symbol))
+            .collect();
+
+        let mut levels = Vec::new();
+        Self::build_level(&mut levels, &codes);
+        Self { levels }
+    }
+
+    /// Adds the level for `codes`, which all share the bits already consumed, and returns
+    /// its index.
+    fn build_level(levels: &mut Vec<Level>, codes: &[(&[bool], Symbol)]) -> usize {
+        let max_length = codes.iter().map(|(code, _)| code.len()).max().unwrap_or(0) as u32;
+        let bits = LEVEL_BITS.min(max_length);
+
+        let index = levels.len();
+        levels.push(Level {
+            bits,
+            entries: Vec::new(),
+        });
+
+        let mut entries = vec![TableEntry::Empty; 1 << bits];
+        let mut longer: BTreeMap<usize, Vec<(&[bool], Symbol)>> = BTreeMap::new();
+
+        for &(code, symbol) in codes {
+            let length = code.len() as u32;
+            let prefix = code[..length.min(bits) as usize]
+                .iter()
+                .fold(0usize, |value, &bit| (value << 1) | bit as usize);
+
+            if length <= bits {
+                // Every index that starts with this code resolves to the same symbol.
+                let shift = bits - length;
+                let start = prefix << shift;
+                for entry in &mut entries[start..start + (1 << shift)] {
+                    *entry = TableEntry::Short(symbol, length as u8);
+                }
+            } else {
+                longer
+                    .entry(prefix)
+                    .or_default()
+                    .push((&code[bits as usize..], symbol));
+            }
+        }
+
+        for (prefix, rest) in longer {
+            entries[prefix] = TableEntry::Next(Self::build_level(levels, &rest));
+        }
+
+        levels[index].entries = entries;
+        index
+    }
+
+    pub fn decode_symbol(&self, reader: &mut BitReader) -> Result<Symbol, HuffmanError> {
+        let mut level = &self.levels[0];
+
+        loop {
+            match level.entries[reader.peek_n_bits(level.bits) as usize] {
+                TableEntry::Short(symbol, length) => {
+                    if reader.bits_remaining() < length as usize {
+                        return Err(HuffmanError::PrematureEnd);
+                    }
+
+                    reader.skip_bits(length as u32);
+                    return Ok(symbol);
+                }
+                TableEntry::Next(next) => {
+                    if reader.bits_remaining() < level.bits as usize {
+                        return Err(HuffmanError::PrematureEnd);
+                    }
+
+                    reader.skip_bits(level.bits);
+                    level = &self.levels[next];
+                }
+                // Past the end the peeked bits are zero padding, so a miss there means missing data.
+                TableEntry::Empty if reader.bits_remaining() < level.bits as usize => {
+                    return Err(HuffmanError::PrematureEnd);
+                }
+                TableEntry::Empty => return Err(HuffmanError::InvalidCode),
+            }
+        }
+    }
+}
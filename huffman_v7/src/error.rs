@@ -0,0 +1,55 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HuffmanError {
+    TruncatedHeader,
+    InvalidHeader,
+    InvalidTree,
+    InvalidCode,
+    PrematureEnd,
+    TrailingGarbage,
+    InputTooLarge,
+    UnknownTable,
+    InvalidUtf8,
+    IncompatibleOptions,
+}
+
+impl HuffmanError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HuffmanError::TruncatedHeader => "truncated_header",
+            HuffmanError::InvalidHeader => "invalid_header",
+            HuffmanError::InvalidTree => "invalid_tree",
+            HuffmanError::InvalidCode => "invalid_code",
+            HuffmanError::PrematureEnd => "premature_end",
+            HuffmanError::TrailingGarbage => "trailing_garbage",
+            HuffmanError::InputTooLarge => "input_too_large",
+            HuffmanError::UnknownTable => "unknown_table",
+            HuffmanError::InvalidUtf8 => "invalid_utf8",
+            HuffmanError::IncompatibleOptions => "incompatible_options",
+        }
+    }
+}
+
+impl fmt::Display for HuffmanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            HuffmanError::TruncatedHeader => "the header ends before all of its fields were read",
+            HuffmanError::InvalidHeader => "the header holds an unknown mode or an invalid field",
+            HuffmanError::InvalidTree => {
+                "the header describes a code table that is not a valid Huffman tree"
+            }
+            HuffmanError::InvalidCode => "the data holds a bit sequence that matches no code",
+            HuffmanError::PrematureEnd => "the data ends before all symbols were decoded",
+            HuffmanError::TrailingGarbage => "unexpected bytes follow the encoded data",
+            HuffmanError::InputTooLarge => "the input has more symbols than the format can count",
+            HuffmanError::UnknownTable => "the file refers to a code table that is not stored here",
+            HuffmanError::InvalidUtf8 => "word symbols need the input to be valid UTF-8 text",
+            HuffmanError::IncompatibleOptions => "the selected encoding options cannot be combined",
+        };
+
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for HuffmanError {}
@@ -1,6 +1,37 @@
+use crate::adaptive::AdaptiveHuffman;
 use crate::bit_operations::{BitReader, BitWriter};
+use crate::canonical;
+use crate::decoder::TableDecoder;
+use crate::error::HuffmanError;
+use crate::tables::{StaticTable, TableStore};
 use crate::tree::{Node, Symbol};
-use std::collections::{BinaryHeap, HashMap};
+use crate::words;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+
+const HSA_MAGIC: &[u8; 3] = b"HSA";
+const HSA_VERSION: u8 = 2;
+const CODE_LENGTH_FIELD_BITS: u32 = 6;
+const MAX_SCALED_FREQUENCY: u32 = 255;
+const SYMBOL_COUNT_FIELD_BITS: u32 = 17;
+const CONTEXT_SYMBOL_COUNT_FIELD_BITS: u32 = 9;
+const CODE_POINT_COUNT_FIELD_BITS: u32 = 21;
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderFormat {
+    Frequencies,
+    CodeLengths,
+    Adaptive,
+    Blocks,
+    ParallelBlocks,
+    StaticTable,
+}
+
+struct EncodedBlock {
+    data: Vec<u8>,
+    table_bits: usize,
+    codes: HashMap<Symbol, Vec<bool>>,
+}
 
 #[derive(Debug, Clone)]
 pub struct CompressionStats {
@@ -13,13 +44,53 @@ pub struct CompressionStats {
     pub percentage_saved: f64,
 }
 
+impl CompressionStats {
+    pub fn new(original_size: usize, compressed_size: usize, header_size: usize) -> Self {
+        let compressed_data_size = compressed_size.saturating_sub(header_size);
+
+        Self {
+            original_size,
+            compressed_size,
+            header_size,
+            compressed_data_size,
+            compression_ratio: if original_size > 0 {
+                ((original_size.saturating_sub(compressed_size)) as f64 / original_size as f64)
+                    * 100.0
+            } else {
+                0.0
+            },
+            space_saved: original_size.saturating_sub(compressed_data_size),
+            percentage_saved: if original_size > 0 {
+                ((original_size.saturating_sub(compressed_data_size)) as f64 / original_size as f64)
+                    * 100.0
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Huffman {
     one_byte_counters: HashMap<u8, u32>,
     two_bytes_counters: HashMap<u16, u32>,
+    context_counters: HashMap<(u8, u8), u32>,
+    code_point_counters: HashMap<char, u32>,
+    word_counters: BTreeMap<String, u32>,
+    words: Vec<String>,
     symbol: Symbol,
     last_codes: Option<HashMap<Symbol, Vec<bool>>>,
+    last_frequencies: Option<Vec<(Symbol, u32)>>,
     header_size: usize,
+    header_format: HeaderFormat,
+    max_code_length: Option<u8>,
+    scale_frequencies: bool,
+    trailing_byte: Option<u8>,
+    byte_fallback: bool,
+    block_size: usize,
+    static_table: Option<StaticTable>,
+    table_store: Option<TableStore>,
+    shannon_fano: bool,
 }
 
 impl Huffman {
@@ -27,14 +98,73 @@ impl Huffman {
         Self {
             one_byte_counters: HashMap::new(),
             two_bytes_counters: HashMap::new(),
+            context_counters: HashMap::new(),
+            code_point_counters: HashMap::new(),
+            word_counters: BTreeMap::new(),
+            words: Vec::new(),
             symbol,
             last_codes: None,
+            last_frequencies: None,
             header_size: 0,
+            header_format: HeaderFormat::Frequencies,
+            max_code_length: None,
+            scale_frequencies: false,
+            trailing_byte: None,
+            byte_fallback: false,
+            block_size: DEFAULT_BLOCK_SIZE,
+            static_table: None,
+            table_store: None,
+            shannon_fano: false,
         }
     }
 
+    pub fn with_header_format(mut self, header_format: HeaderFormat) -> Self {
+        self.header_format = header_format;
+        self
+    }
+
+    pub fn with_max_code_length(mut self, max_code_length: Option<u8>) -> Self {
+        self.max_code_length =
+            max_code_length.map(|length| length.clamp(1, (1 << CODE_LENGTH_FIELD_BITS) - 1));
+        self
+    }
+
+    pub fn with_frequency_scaling(mut self, scale_frequencies: bool) -> Self {
+        self.scale_frequencies = scale_frequencies;
+        self
+    }
+
+    pub fn with_block_size(mut self, block_size: Option<usize>) -> Self {
+        self.block_size = block_size
+            .unwrap_or(DEFAULT_BLOCK_SIZE)
+            .clamp(1, u32::MAX as usize);
+        self
+    }
+
+    /// Builds Shannon-Fano code lengths instead of Huffman ones. Decoders cannot rebuild them
+    /// from frequencies, so the header stores the lengths. The splits cannot honour a maximum
+    /// code length, so `compress` rejects the combination.
+    pub fn with_shannon_fano(mut self, shannon_fano: bool) -> Self {
+        self.shannon_fano = shannon_fano;
+        self
+    }
+
+    /// Encodes with a pre-trained table instead of one built from the input. The table only
+    /// codes the symbol mode it was trained for and replaces the header, so `compress` rejects
+    /// any other mode and any header format but the default.
+    pub fn with_static_table(mut self, table: StaticTable) -> Self {
+        self.static_table = Some(table);
+        self
+    }
+
+    /// Where `decompress` looks up the tables that static-table files refer to.
+    pub fn with_table_store(mut self, table_store: TableStore) -> Self {
+        self.table_store = Some(table_store);
+        self
+    }
+
     pub fn count_frequencies(&mut self, file_data: &[u8]) {
-        let mut reader: BitReader = BitReader::new(&file_data);
+        let mut reader: BitReader = BitReader::new(file_data);
 
         match self.symbol {
             Symbol::OneByte(_) => {
@@ -47,30 +177,46 @@ impl Huffman {
                     *self.two_bytes_counters.entry(two_bytes as u16).or_insert(0) += 1;
                 }
             }
+            Symbol::Context(..) => {
+                let mut previous = 0u8;
+                while let Some(byte) = reader.read_n_bits(8) {
+                    *self
+                        .context_counters
+                        .entry((previous, byte as u8))
+                        .or_insert(0) += 1;
+                    previous = byte as u8;
+                }
+            }
+            Symbol::Word(_) => {
+                // `compress` rejects non-UTF-8 input before counting, so nothing is replaced here.
+                for token in words::tokenize(&String::from_utf8_lossy(file_data)) {
+                    *self.word_counters.entry(token.to_string()).or_insert(0) += 1;
+                }
+
+                // Word IDs are positions in the sorted dictionary.
+                self.words = self.word_counters.keys().cloned().collect();
+            }
+            Symbol::CodePoint(_) => {
+                // `compress` falls back to bytes for non-UTF-8 input; elsewhere invalid
+                // sequences are counted as U+FFFD.
+                for c in String::from_utf8_lossy(file_data).chars() {
+                    *self.code_point_counters.entry(c).or_insert(0) += 1;
+                }
+            }
         }
     }
 
-    pub fn build_huffman_tree(&self) -> Node {
+    /// Returns `None` when no symbol has been counted.
+    pub fn build_huffman_tree(&self) -> Option<Node> {
+        Self::build_tree(&self.frequencies())
+    }
+
+    fn build_tree(frequencies: &[(Symbol, u32)]) -> Option<Node> {
         let mut heap = BinaryHeap::new();
 
-        match self.symbol {
-            Symbol::OneByte(_) => {
-                for (&byte, &frequency) in self.one_byte_counters.iter() {
-                    if frequency > 0 {
-                        let symbol = Symbol::OneByte(byte);
-                        let leaf = Node::new(symbol, frequency);
-                        heap.push(leaf);
-                    }
-                }
-            }
-            Symbol::TwoBytes(_) => {
-                for (&symbol_value, &frequency) in self.two_bytes_counters.iter() {
-                    if frequency > 0 {
-                        let symbol = Symbol::TwoBytes(symbol_value);
-                        let leaf = Node::new(symbol, frequency);
-                        heap.push(leaf);
-                    }
-                }
+        for &(symbol, frequency) in frequencies {
+            if frequency > 0 {
+                heap.push(Node::new(symbol, frequency));
             }
         }
 
@@ -82,42 +228,188 @@ impl Huffman {
         }
 
         heap.pop()
-            .expect("Huffman tree should have at least one node")
     }
 
-    fn generate_huffman_codes(&self, root: &Node) -> HashMap<Symbol, Vec<bool>> {
-        let mut codes = HashMap::new();
-        let mut current_code = Vec::new();
-        self.generate_codes_recursive(root, &mut current_code, &mut codes);
-        codes
+    /// Builds the tree of the codes `compress` would write for `file_data`, or returns `None`
+    /// when the input holds no complete symbol. The Huffman tree only fixes the code lengths,
+    /// so the tree is rebuilt from the canonical codes to put every leaf at its code's path.
+    pub fn frequency_tree(&mut self, file_data: &[u8]) -> Option<Node> {
+        self.clear_counters();
+        self.count_frequencies(file_data);
+
+        let codes = self.assign_codes(&self.compute_code_lengths());
+        let leaves: Vec<(Vec<bool>, Node)> = self
+            .frequencies()
+            .into_iter()
+            .filter_map(|(symbol, frequency)| {
+                let code = codes.get(&symbol)?.clone();
+                Some((code, Node::new(symbol, frequency)))
+            })
+            .collect();
+
+        (!leaves.is_empty()).then(|| tree_from_codes(leaves, 0))
     }
 
-    fn generate_codes_recursive(
-        &self,
-        node: &Node,
-        current_code: &mut Vec<bool>,
-        codes: &mut HashMap<Symbol, Vec<bool>>,
-    ) {
-        if node.is_leaf() {
-            codes.insert(node.symbol, current_code.clone());
-            return;
+    /// Code lengths for a pre-trained table. Every symbol of the alphabet is counted once on
+    /// top of the samples, so inputs holding symbols the samples lacked still encode.
+    pub fn train_code_lengths(&mut self, samples: &[Vec<u8>]) -> HashMap<Symbol, u8> {
+        self.clear_counters();
+        for sample in samples {
+            self.count_frequencies(sample);
+        }
+
+        match self.symbol {
+            Symbol::OneByte(_) => {
+                for byte in 0u8..=255u8 {
+                    *self.one_byte_counters.entry(byte).or_insert(0) += 1;
+                }
+            }
+            Symbol::TwoBytes(_) => {
+                for value in 0u16..=u16::MAX {
+                    *self.two_bytes_counters.entry(value).or_insert(0) += 1;
+                }
+            }
+            Symbol::Context(..) => {
+                for previous in 0u8..=255u8 {
+                    for byte in 0u8..=255u8 {
+                        *self.context_counters.entry((previous, byte)).or_insert(0) += 1;
+                    }
+                }
+            }
+            // Words have no fixed alphabet to smooth over, and code points too large a one.
+            Symbol::Word(_) | Symbol::CodePoint(_) => {}
+        }
+
+        self.compute_code_lengths()
+    }
+
+    fn clear_counters(&mut self) {
+        self.one_byte_counters.clear();
+        self.two_bytes_counters.clear();
+        self.context_counters.clear();
+        self.code_point_counters.clear();
+        self.word_counters.clear();
+        self.words.clear();
+    }
+
+    pub fn frequencies(&self) -> Vec<(Symbol, u32)> {
+        let mut frequencies: Vec<(Symbol, u32)> = match self.symbol {
+            Symbol::OneByte(_) => self
+                .one_byte_counters
+                .iter()
+                .map(|(&byte, &frequency)| (Symbol::OneByte(byte), frequency))
+                .collect(),
+            Symbol::TwoBytes(_) => self
+                .two_bytes_counters
+                .iter()
+                .map(|(&value, &frequency)| (Symbol::TwoBytes(value), frequency))
+                .collect(),
+            Symbol::Context(..) => self
+                .context_counters
+                .iter()
+                .map(|(&(previous, byte), &frequency)| (Symbol::Context(previous, byte), frequency))
+                .collect(),
+            Symbol::Word(_) => self
+                .word_counters
+                .values()
+                .enumerate()
+                .map(|(id, &frequency)| (Symbol::Word(id as u32), frequency))
+                .collect(),
+            Symbol::CodePoint(_) => self
+                .code_point_counters
+                .iter()
+                .map(|(&c, &frequency)| (Symbol::CodePoint(c), frequency))
+                .collect(),
+        };
+
+        frequencies.sort();
+        frequencies
+    }
+
+    pub fn compute_code_lengths(&self) -> HashMap<Symbol, u8> {
+        match self.symbol {
+            Symbol::Context(..) => {
+                let mut by_context: Vec<Vec<(Symbol, u32)>> = vec![Vec::new(); 256];
+                for (symbol, frequency) in self.frequencies() {
+                    if let Symbol::Context(previous, _) = symbol {
+                        by_context[previous as usize].push((symbol, frequency));
+                    }
+                }
+
+                by_context
+                    .iter()
+                    .flat_map(|frequencies| self.code_lengths_for(frequencies))
+                    .collect()
+            }
+            _ => self.code_lengths_for(&self.frequencies()),
+        }
+    }
+
+    /// Whether every code table of `file_data` can keep its codes within the maximum code
+    /// length; `n` symbols need at least `bits_needed(n - 1)` bits. Blocks only ever hold a
+    /// subset of the file's symbols, so checking the whole file covers them too.
+    fn max_code_length_fits(&mut self, file_data: &[u8]) -> bool {
+        let Some(max_length) = self.max_code_length else {
+            return true;
+        };
+
+        self.clear_counters();
+        self.count_frequencies(file_data);
+        let mut table_sizes: HashMap<Option<u8>, u32> = HashMap::new();
+        for (symbol, _) in self.frequencies() {
+            let table = match symbol {
+                Symbol::Context(previous, _) => Some(previous),
+                _ => None,
+            };
+            *table_sizes.entry(table).or_insert(0) += 1;
+        }
+        self.clear_counters();
+
+        table_sizes
+            .values()
+            .all(|&size| canonical::bits_needed(size - 1) <= max_length as u32)
+    }
+
+    fn code_lengths_for(&self, frequencies: &[(Symbol, u32)]) -> HashMap<Symbol, u8> {
+        if frequencies.iter().all(|&(_, frequency)| frequency == 0) {
+            return HashMap::new();
+        }
+
+        if self.shannon_fano {
+            return canonical::shannon_fano_code_lengths(frequencies);
         }
 
-        if let Some(ref left) = node.left {
-            current_code.push(false);
-            println!("Current code: {:?}", current_code);
-            self.generate_codes_recursive(left, current_code, codes);
-            current_code.pop();
+        match self.max_code_length {
+            Some(max_length) => canonical::length_limited_code_lengths(frequencies, max_length),
+            None => Self::build_tree(frequencies)
+                .map(|tree| canonical::code_lengths_from_tree(&tree))
+                .unwrap_or_default(),
         }
+    }
 
-        if let Some(ref right) = node.right {
-            current_code.push(true);
-            println!("Current code: {:?}", current_code);
-            self.generate_codes_recursive(right, current_code, codes);
-            current_code.pop();
+    /// Context codes are canonical within each preceding byte's table, not across the whole map.
+    fn assign_codes(&self, lengths: &HashMap<Symbol, u8>) -> HashMap<Symbol, Vec<bool>> {
+        match self.symbol {
+            Symbol::Context(..) => split_by_context(lengths)
+                .iter()
+                .flat_map(canonical::assign_canonical_codes)
+                .collect(),
+            _ => canonical::assign_canonical_codes(lengths),
         }
     }
 
+    /// Size of the coded data under `lengths`, or `None` when a counted symbol has no code.
+    fn payload_bits(&self, lengths: &HashMap<Symbol, u8>) -> Option<u64> {
+        self.frequencies()
+            .iter()
+            .map(|(symbol, frequency)| {
+                lengths
+                    .get(symbol)
+                    .map(|&length| *frequency as u64 * length as u64)
+            })
+            .sum()
+    }
+
     fn write_codes(
         &self,
         reader: &mut BitReader,
@@ -145,13 +437,60 @@ impl Huffman {
                     }
                 }
             }
+            Symbol::Context(..) => {
+                let mut previous = 0u8;
+                while let Some(byte) = reader.read_n_bits(8) {
+                    let symbol = Symbol::Context(previous, byte as u8);
+                    if let Some(code) = codes.get(&symbol) {
+                        for &bit in code {
+                            writer.write_bit(bit);
+                        }
+                    }
+                    previous = byte as u8;
+                }
+            }
+            Symbol::Word(_) => {
+                let mut text = Vec::new();
+                while let Some(byte) = reader.read_n_bits(8) {
+                    text.push(byte as u8);
+                }
+
+                let ids: HashMap<&str, u32> = self
+                    .words
+                    .iter()
+                    .enumerate()
+                    .map(|(id, word)| (word.as_str(), id as u32))
+                    .collect();
+                for token in words::tokenize(&String::from_utf8_lossy(&text)) {
+                    if let Some(code) = ids.get(token).and_then(|&id| codes.get(&Symbol::Word(id)))
+                    {
+                        for &bit in code {
+                            writer.write_bit(bit);
+                        }
+                    }
+                }
+            }
+            Symbol::CodePoint(_) => {
+                while let Some(symbol) = self.read_symbol(reader) {
+                    if let Some(code) = codes.get(&symbol) {
+                        for &bit in code {
+                            writer.write_bit(bit);
+                        }
+                    }
+                }
+            }
         }
     }
 
     fn write_frequencies_in_header(&self, writer: &mut BitWriter) {
         match self.symbol {
             Symbol::OneByte(_) => self.write_frequency_header_on_one_byte(writer),
-            Symbol::TwoBytes(_) => self.write_frequency_header_on_two_bytes(writer),
+            Symbol::TwoBytes(_) | Symbol::CodePoint(_) => {
+                self.write_sparse_frequency_header(writer)
+            }
+            Symbol::Context(..) | Symbol::Word(_) => {
+                unreachable!("context and word tables are always stored as code lengths")
+            }
         }
     }
 
@@ -165,27 +504,26 @@ impl Huffman {
         }
     }
 
-    fn write_frequency_header_on_two_bytes(&self, writer: &mut BitWriter) {
-        for symbol in 0..65536u32 {
-            let exists = self.two_bytes_counters.contains_key(&(symbol as u16));
-            writer.write_bit(exists);
-        }
+    fn write_sparse_frequency_header(&self, writer: &mut BitWriter) {
+        let present: Vec<(Symbol, u32)> = self.frequencies();
+        let symbols: Vec<Symbol> = present.iter().map(|&(symbol, _)| symbol).collect();
 
-        for symbol in 0..65536u32 {
-            if let Some(&frequency) = self.two_bytes_counters.get(&(symbol as u16)) {
-                if frequency <= 255 {
-                    writer.write_bit(false);
-                } else {
-                    writer.write_bit(true);
-                }
-            }
+        self.write_present_symbols(writer, &symbols);
+
+        for &(_, frequency) in &present {
+            writer.write_n_bits(self.get_frequency_encoding(frequency) as u32, 2);
         }
     }
 
     fn write_frequency_values_in_header(&self, writer: &mut BitWriter) {
         match self.symbol {
             Symbol::OneByte(_) => self.write_frequency_value_on_one_byte(writer),
-            Symbol::TwoBytes(_) => self.write_frequency_values_on_two_bytes(writer),
+            Symbol::TwoBytes(_) | Symbol::CodePoint(_) => {
+                self.write_sparse_frequency_values(writer)
+            }
+            Symbol::Context(..) | Symbol::Word(_) => {
+                unreachable!("context and word tables are always stored as code lengths")
+            }
         }
     }
 
@@ -203,25 +541,130 @@ impl Huffman {
                     writer.write_n_bits(frequency, 16);
                 }
                 0b11 => {
-                    panic!("Frequency {} higher then 65,535", frequency)
+                    writer.write_n_bits(frequency, 32);
                 }
                 _ => unreachable!(),
             }
         }
     }
 
-    fn write_frequency_values_on_two_bytes(&self, writer: &mut BitWriter) {
-        for symbol in 0..65536u32 {
-            if let Some(&frequency) = self.two_bytes_counters.get(&(symbol as u16)) {
-                if frequency <= 255 {
-                    writer.write_n_bits(frequency, 8);
-                } else if frequency <= 65535 {
-                    writer.write_n_bits(frequency, 16);
-                } else {
-                    writer.write_n_bits(frequency, 32);
+    fn write_sparse_frequency_values(&self, writer: &mut BitWriter) {
+        for (_, frequency) in self.frequencies() {
+            match self.get_frequency_encoding(frequency) {
+                0b01 => writer.write_n_bits(frequency, 8),
+                0b10 => writer.write_n_bits(frequency, 16),
+                _ => writer.write_n_bits(frequency, 32),
+            }
+        }
+    }
+
+    /// One-byte tables use a 256-bit presence bitmap; two-byte, context and code point tables
+    /// list the present symbols as Elias-gamma coded gaps so sparse tables stay small.
+    pub fn write_present_symbols(&self, writer: &mut BitWriter, symbols: &[Symbol]) {
+        match self.symbol {
+            Symbol::OneByte(_) => {
+                for byte in 0u8..=255u8 {
+                    writer.write_bit(symbols.contains(&Symbol::OneByte(byte)));
+                }
+            }
+            Symbol::TwoBytes(_) => {
+                let values: Vec<u32> = symbols
+                    .iter()
+                    .filter_map(|symbol| match *symbol {
+                        Symbol::TwoBytes(value) => Some(value as u32),
+                        _ => None,
+                    })
+                    .collect();
+                write_gap_list(writer, &values, SYMBOL_COUNT_FIELD_BITS);
+            }
+            Symbol::Context(..) => {
+                let values: Vec<u32> = symbols
+                    .iter()
+                    .filter_map(|symbol| match *symbol {
+                        Symbol::Context(_, byte) => Some(byte as u32),
+                        _ => None,
+                    })
+                    .collect();
+                write_gap_list(writer, &values, CONTEXT_SYMBOL_COUNT_FIELD_BITS);
+            }
+            Symbol::CodePoint(_) => {
+                let values: Vec<u32> = symbols
+                    .iter()
+                    .filter_map(|symbol| match *symbol {
+                        Symbol::CodePoint(c) => Some(c as u32),
+                        _ => None,
+                    })
+                    .collect();
+                write_gap_list(writer, &values, CODE_POINT_COUNT_FIELD_BITS);
+            }
+            // Every dictionary word has a code, so the dictionary already says what is present.
+            Symbol::Word(_) => {}
+        }
+    }
+
+    /// `table` names the symbol space being read; for context tables it also carries the
+    /// preceding byte the table belongs to, and for word tables the dictionary size.
+    pub fn read_present_symbols(
+        reader: &mut BitReader,
+        table: Symbol,
+    ) -> Result<Vec<Symbol>, HuffmanError> {
+        let symbols = match table {
+            Symbol::OneByte(_) => {
+                let mut symbols = Vec::new();
+                for byte in 0u8..=255u8 {
+                    if read_header_bit(reader)? {
+                        symbols.push(Symbol::OneByte(byte));
+                    }
                 }
+                symbols
+            }
+            Symbol::TwoBytes(_) => read_gap_list(reader, SYMBOL_COUNT_FIELD_BITS, u16::MAX as u32)?
+                .into_iter()
+                .map(|value| Symbol::TwoBytes(value as u16))
+                .collect(),
+            Symbol::Context(previous, _) => {
+                read_gap_list(reader, CONTEXT_SYMBOL_COUNT_FIELD_BITS, u8::MAX as u32)?
+                    .into_iter()
+                    .map(|byte| Symbol::Context(previous, byte as u8))
+                    .collect()
             }
+            Symbol::Word(count) => (0..count).map(Symbol::Word).collect(),
+            // Surrogates fall inside the gap list's range but are not scalar values.
+            Symbol::CodePoint(_) => {
+                read_gap_list(reader, CODE_POINT_COUNT_FIELD_BITS, char::MAX as u32)?
+                    .into_iter()
+                    .map(|value| char::from_u32(value).map(Symbol::CodePoint))
+                    .collect::<Option<Vec<Symbol>>>()
+                    .ok_or(HuffmanError::InvalidHeader)?
+            }
+        };
+
+        Ok(symbols)
+    }
+
+    /// Maps every count into 1..=MAX_SCALED_FREQUENCY so each one fits the 8-bit size class.
+    fn scale_counters(&mut self) {
+        let max_frequency = self
+            .frequencies()
+            .iter()
+            .map(|&(_, frequency)| frequency)
+            .max()
+            .unwrap_or(0) as u64;
+
+        if max_frequency <= MAX_SCALED_FREQUENCY as u64 {
+            return;
         }
+
+        let scale = |frequency: &mut u32| {
+            let scaled = (*frequency as u64 * MAX_SCALED_FREQUENCY as u64 + max_frequency / 2)
+                / max_frequency;
+            *frequency = scaled.max(1) as u32;
+        };
+
+        self.one_byte_counters.values_mut().for_each(scale);
+        self.two_bytes_counters.values_mut().for_each(scale);
+        self.context_counters.values_mut().for_each(scale);
+        self.code_point_counters.values_mut().for_each(scale);
     }
 
     fn get_frequency_encoding(&self, frequency: u32) -> u8 {
@@ -236,95 +679,873 @@ impl Huffman {
         }
     }
 
-    pub fn compress(&mut self, file_data: &[u8]) -> Vec<u8> {
+    pub fn compress(&mut self, file_data: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+        // Symbol totals and frequencies are stored as 32-bit fields.
+        if file_data.len() > u32::MAX as usize {
+            return Err(HuffmanError::InputTooLarge);
+        }
+
+        if self.shannon_fano && self.max_code_length.is_some() {
+            return Err(HuffmanError::IncompatibleOptions);
+        }
+
+        if let Some(table) = &self.static_table {
+            let default_format = matches!(
+                self.header_format,
+                HeaderFormat::Frequencies | HeaderFormat::StaticTable
+            );
+            if table.symbol != self.symbol || !default_format {
+                return Err(HuffmanError::IncompatibleOptions);
+            }
+            self.header_format = HeaderFormat::StaticTable;
+        }
+
+        if matches!(self.symbol, Symbol::Word(_)) && std::str::from_utf8(file_data).is_err() {
+            return Err(HuffmanError::InvalidUtf8);
+        }
+        // Input that is not UTF-8 has no code points to count, so it is coded byte by byte and
+        // the header keeps the code point mode with a flag saying so.
+        self.byte_fallback =
+            matches!(self.symbol, Symbol::CodePoint(_)) && std::str::from_utf8(file_data).is_err();
+        if self.byte_fallback {
+            self.symbol = Symbol::OneByte(0);
+        }
+
         let mut writer = BitWriter::new();
         let mut reader = BitReader::new(file_data);
 
+        // Two-byte symbols cannot hold a final odd byte, so it travels in the header instead.
+        self.trailing_byte = match self.symbol {
+            Symbol::TwoBytes(_) if file_data.len() % 2 == 1 => file_data.last().copied(),
+            _ => None,
+        };
+
+        // Tokens can straddle block boundaries, and adaptive or shared tables cannot hold the
+        // file's own dictionary, so words always ship one code length table. Frequency headers
+        // are the default and get upgraded; any other requested format is refused.
+        if let Symbol::Word(_) = self.symbol {
+            match self.header_format {
+                HeaderFormat::Frequencies | HeaderFormat::CodeLengths => {
+                    self.header_format = HeaderFormat::CodeLengths;
+                }
+                _ => return Err(HuffmanError::IncompatibleOptions),
+            }
+        }
+
+        // 256 frequency tables would dwarf the payload, so context tables only ship code
+        // lengths, and the adaptive coder keeps a single tree, which has no contexts.
+        if let Symbol::Context(..) = self.symbol {
+            match self.header_format {
+                HeaderFormat::Frequencies => self.header_format = HeaderFormat::CodeLengths,
+                HeaderFormat::Adaptive => return Err(HuffmanError::IncompatibleOptions),
+                _ => {}
+            }
+        }
+        let blocked = matches!(
+            self.header_format,
+            HeaderFormat::Blocks | HeaderFormat::ParallelBlocks
+        );
+        if self.shannon_fano && self.header_format == HeaderFormat::Frequencies {
+            self.header_format = HeaderFormat::CodeLengths;
+        }
+
+        let own_lengths = !matches!(
+            self.header_format,
+            HeaderFormat::Adaptive | HeaderFormat::StaticTable
+        );
+        if own_lengths && !self.max_code_length_fits(file_data) {
+            return Err(HuffmanError::IncompatibleOptions);
+        }
+
+        // The trailing byte is already in the header, so the total only counts whole symbols.
+        let whole_symbols = &file_data[..file_data.len() - file_data.len() % self.symbol_size()];
+        let total_symbols = match self.symbol {
+            Symbol::Word(_) => words::tokenize(&String::from_utf8_lossy(file_data)).count() as u32,
+            Symbol::CodePoint(_) => String::from_utf8_lossy(file_data).chars().count() as u32,
+            _ => (whole_symbols.len() / self.symbol_size()) as u32,
+        };
+        self.write_container_header(&mut writer, total_symbols);
+
+        match self.header_format {
+            HeaderFormat::Blocks => self.write_blocks(whole_symbols, &mut writer),
+            HeaderFormat::ParallelBlocks => self.write_parallel_blocks(whole_symbols, &mut writer),
+            _ => {}
+        }
+        if blocked {
+            return Ok(writer.finish());
+        }
+
+        if self.header_format == HeaderFormat::Adaptive {
+            self.header_size = writer.data.len();
+            self.write_adaptive_codes(&mut reader, &mut writer);
+            return Ok(writer.finish());
+        }
+
         self.count_frequencies(file_data);
 
-        match self.symbol {
-            Symbol::OneByte(_) => writer.write_bit(false),
-            Symbol::TwoBytes(_) => writer.write_bit(true),
+        let scale_frequencies =
+            self.scale_frequencies && self.header_format == HeaderFormat::Frequencies;
+        if scale_frequencies {
+            self.scale_counters();
         }
 
-        self.write_frequencies_in_header(&mut writer);
-        self.write_frequency_values_in_header(&mut writer);
+        let lengths = match &self.static_table {
+            Some(table) if self.header_format == HeaderFormat::StaticTable => table.lengths.clone(),
+            _ => self.compute_code_lengths(),
+        };
+
+        match self.header_format {
+            HeaderFormat::Frequencies => {
+                writer.write_n_bits(
+                    self.max_code_length.unwrap_or(0) as u32,
+                    CODE_LENGTH_FIELD_BITS,
+                );
+                writer.write_bit(scale_frequencies);
+                self.write_frequencies_in_header(&mut writer);
+                self.write_frequency_values_in_header(&mut writer);
+            }
+            HeaderFormat::CodeLengths => {
+                if let Symbol::Word(_) = self.symbol {
+                    self.write_word_dictionary(&mut writer);
+                }
+                self.write_code_length_tables(&mut writer, &lengths);
+            }
+            HeaderFormat::StaticTable => self.write_table_reference(&mut writer),
+            HeaderFormat::Adaptive | HeaderFormat::Blocks | HeaderFormat::ParallelBlocks => {
+                unreachable!()
+            }
+        }
 
         self.header_size = writer.data.len() + if writer.bit_position > 0 { 1 } else { 0 };
 
-        let tree = self.build_huffman_tree();
-        let codes = self.generate_huffman_codes(&tree);
+        let codes = self.assign_codes(&lengths);
 
         self.last_codes = Some(codes.clone());
 
         self.write_codes(&mut reader, &mut writer, &codes);
 
-        writer.finish()
+        Ok(writer.finish())
     }
 
-    pub fn decompress(&mut self, file_data: &[u8]) -> Vec<u8> {
-        let mut reader = BitReader::new(file_data);
-        let mut writer = BitWriter::new();
+    /// Every format but the adaptive one, which ends on its own end-of-stream code, stores
+    /// the symbol total here so decoding never has to trust the table that follows.
+    fn write_container_header(&self, writer: &mut BitWriter, total_symbols: u32) {
+        for &byte in HSA_MAGIC {
+            writer.write_n_bits(byte as u32, 8);
+        }
+        writer.write_n_bits(HSA_VERSION as u32, 8);
 
-        let mode_bit = reader.read_bit().unwrap_or(false);
-        self.symbol = if mode_bit {
-            Symbol::TwoBytes(0)
-        } else {
-            Symbol::OneByte(0)
+        let symbol_mode = match self.symbol {
+            Symbol::OneByte(_) if self.byte_fallback => 4,
+            Symbol::OneByte(_) => 0,
+            Symbol::TwoBytes(_) => 1,
+            Symbol::Context(..) => 2,
+            Symbol::Word(_) => 3,
+            Symbol::CodePoint(_) => 4,
+        };
+        let header_format = match self.header_format {
+            HeaderFormat::Frequencies => 0,
+            HeaderFormat::CodeLengths => 1,
+            HeaderFormat::Adaptive => 2,
+            HeaderFormat::Blocks => 3,
+            HeaderFormat::ParallelBlocks => 4,
+            HeaderFormat::StaticTable => 5,
         };
 
-        self.read_frequency_header(&mut reader);
-
-        let tree = self.build_huffman_tree();
-
-        let total_symbols = self.calculate_total_symbols();
-
-        let mut decoded_symbols = 0;
-        let mut current_node = &tree;
-
-        while decoded_symbols < total_symbols {
-            let bit = match reader.read_bit() {
-                Some(b) => b,
-                None => break,
-            };
+        writer.write_n_bits(symbol_mode, 4);
+        writer.write_n_bits(header_format, 4);
 
-            if bit == false {
-                current_node = current_node.left.as_ref().unwrap();
-            } else {
-                current_node = current_node.right.as_ref().unwrap();
-            }
+        if symbol_mode == 4 {
+            writer.write_bit(self.byte_fallback);
+        }
 
-            if current_node.is_leaf() {
-                match current_node.symbol {
-                    Symbol::OneByte(byte) => {
-                        writer.write_n_bits(byte as u32, 8);
-                    }
-                    Symbol::TwoBytes(symbol_val) => {
-                        writer.write_n_bits(symbol_val as u32, 16);
-                    }
-                }
-                decoded_symbols += 1;
-                current_node = &tree;
+        if let Symbol::TwoBytes(_) = self.symbol {
+            writer.write_bit(self.trailing_byte.is_some());
+            if let Some(byte) = self.trailing_byte {
+                writer.write_n_bits(byte as u32, 8);
             }
         }
 
-        writer.finish()
+        if self.header_format != HeaderFormat::Adaptive {
+            writer.write_n_bits(total_symbols, 32);
+        }
     }
 
-    fn read_frequency_header(&mut self, reader: &mut BitReader) {
-        match self.symbol {
-            Symbol::OneByte(_) => self.read_frequency_header_on_one_byte(reader),
-            Symbol::TwoBytes(_) => self.read_frequency_header_on_two_bytes(reader),
+    /// Only the table ID goes into the file; the codes live server-side.
+    fn write_table_reference(&self, writer: &mut BitWriter) {
+        let id = self
+            .static_table
+            .as_ref()
+            .map(|table| table.id.as_bytes())
+            .unwrap_or_default();
+
+        writer.write_n_bits(id.len() as u32, 8);
+        for &byte in id {
+            writer.write_n_bits(byte as u32, 8);
         }
     }
 
-    fn read_frequency_header_on_one_byte(&mut self, reader: &mut BitReader) {
-        self.one_byte_counters.clear();
+    fn read_table_reference(
+        &mut self,
+        reader: &mut BitReader,
+    ) -> Result<HashMap<Symbol, u8>, HuffmanError> {
+        let id_length = read_header_bits(reader, 8)?;
+        let mut id = Vec::with_capacity(id_length as usize);
+        for _ in 0..id_length {
+            id.push(read_header_bits(reader, 8)? as u8);
+        }
 
-        let mut encodings = [0u8; 256];
-        for symbol in 0u8..=255u8 {
-            let high_bit = reader.read_bit().unwrap_or(false);
-            let low_bit = reader.read_bit().unwrap_or(false);
+        let id = String::from_utf8(id).map_err(|_| HuffmanError::InvalidHeader)?;
+        let table = self
+            .table_store
+            .as_ref()
+            .ok_or(HuffmanError::UnknownTable)?
+            .load(&id)?;
+        if table.symbol != self.symbol {
+            return Err(HuffmanError::InvalidHeader);
+        }
+
+        let lengths = table.lengths.clone();
+        self.static_table = Some(table);
+        Ok(lengths)
+    }
+
+    /// The sorted dictionary, front-coded: each word stores how many leading bytes it shares
+    /// with the previous one and then only the bytes that differ.
+    fn write_word_dictionary(&self, writer: &mut BitWriter) {
+        writer.write_elias_gamma(self.words.len() as u32 + 1);
+
+        let mut previous: &[u8] = &[];
+        for word in &self.words {
+            let word = word.as_bytes();
+            let shared = previous
+                .iter()
+                .zip(word)
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            writer.write_elias_gamma(shared as u32 + 1);
+            writer.write_elias_gamma((word.len() - shared) as u32);
+            writer.write_bytes(&word[shared..]);
+            previous = word;
+        }
+    }
+
+    fn read_word_dictionary(&mut self, reader: &mut BitReader) -> Result<(), HuffmanError> {
+        let count = read_header_gamma(reader)? - 1;
+
+        self.words = Vec::new();
+        let mut previous: Vec<u8> = Vec::new();
+        for _ in 0..count {
+            let shared = read_header_gamma(reader)? as usize - 1;
+            let suffix = read_header_gamma(reader)?;
+            if shared > previous.len() {
+                return Err(HuffmanError::InvalidHeader);
+            }
+
+            let mut word = previous[..shared].to_vec();
+            for _ in 0..suffix {
+                word.push(read_header_bits(reader, 8)? as u8);
+            }
+
+            // Sorted and distinct, or two IDs would share a word.
+            if word <= previous && !self.words.is_empty() {
+                return Err(HuffmanError::InvalidHeader);
+            }
+            let text = String::from_utf8(word.clone()).map_err(|_| HuffmanError::InvalidHeader)?;
+            self.words.push(text);
+            previous = word;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_code_length_tables(&self, writer: &mut BitWriter, lengths: &HashMap<Symbol, u8>) {
+        match self.symbol {
+            Symbol::Context(..) => {
+                let tables = split_by_context(lengths);
+                let contexts: Vec<u32> = (0..256u32)
+                    .filter(|&previous| !tables[previous as usize].is_empty())
+                    .collect();
+
+                write_gap_list(writer, &contexts, CONTEXT_SYMBOL_COUNT_FIELD_BITS);
+                for &previous in &contexts {
+                    self.write_length_table(writer, &tables[previous as usize]);
+                }
+            }
+            _ => self.write_length_table(writer, lengths),
+        }
+    }
+
+    fn write_length_table(&self, writer: &mut BitWriter, lengths: &HashMap<Symbol, u8>) {
+        let max_length = lengths.values().copied().max().unwrap_or(0) as u32;
+        let length_bits = canonical::bits_needed(max_length);
+        writer.write_n_bits(max_length, CODE_LENGTH_FIELD_BITS);
+
+        let mut present: Vec<(Symbol, u8)> = lengths
+            .iter()
+            .filter(|(_, &length)| length > 0)
+            .map(|(&symbol, &length)| (symbol, length))
+            .collect();
+        present.sort();
+
+        let symbols: Vec<Symbol> = present.iter().map(|&(symbol, _)| symbol).collect();
+        self.write_present_symbols(writer, &symbols);
+
+        for &(_, length) in &present {
+            writer.write_n_bits(length as u32, length_bits);
+        }
+    }
+
+    pub fn decompress(&mut self, file_data: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+        let is_container = file_data.len() > HSA_MAGIC.len()
+            && file_data.starts_with(HSA_MAGIC)
+            && file_data[HSA_MAGIC.len()] == HSA_VERSION;
+
+        if !is_container {
+            return self.decompress_legacy(file_data);
+        }
+
+        let payload = &file_data[HSA_MAGIC.len() + 1..];
+        let mut reader = BitReader::new(payload);
+        let mut writer = BitWriter::new();
+
+        let total_symbols = self.read_container_header(&mut reader)?;
+
+        let lengths = match self.header_format {
+            HeaderFormat::Frequencies => {
+                let max_code_length = read_header_bits(&mut reader, CODE_LENGTH_FIELD_BITS)?;
+                self.max_code_length = (max_code_length > 0).then_some(max_code_length as u8);
+
+                self.scale_frequencies = read_header_bit(&mut reader)?;
+                self.read_frequency_header(&mut reader)?;
+                // Only guards the tree's 32-bit weights; the symbol total comes from the container.
+                self.header_total_symbols()?;
+                self.last_frequencies = Some(self.frequencies());
+                self.compute_code_lengths()
+            }
+            HeaderFormat::CodeLengths => {
+                if let Symbol::Word(_) = self.symbol {
+                    self.read_word_dictionary(&mut reader)?;
+                }
+                self.read_code_length_tables(&mut reader)?
+            }
+            HeaderFormat::StaticTable => self.read_table_reference(&mut reader)?,
+            HeaderFormat::Adaptive => return self.decompress_adaptive(&mut reader),
+            HeaderFormat::Blocks => return self.decompress_blocks(&mut reader, total_symbols),
+            HeaderFormat::ParallelBlocks => {
+                return self.decompress_parallel_blocks(payload, &mut reader, total_symbols);
+            }
+        };
+
+        let codes = self.assign_codes(&lengths);
+        let decoders = self.build_decoders(&codes);
+
+        self.decode_symbols(&mut reader, &mut writer, &decoders, total_symbols)?;
+        expect_end(&reader)?;
+
+        self.last_codes = Some(codes);
+        Ok(self.finish_output(writer))
+    }
+
+    /// Context mode keeps one decoder per preceding byte; the other modes only use slot 0.
+    fn build_decoders(&self, codes: &HashMap<Symbol, Vec<bool>>) -> Vec<Option<TableDecoder>> {
+        match self.symbol {
+            Symbol::Context(..) => split_by_context(codes)
+                .iter()
+                .map(|table| (!table.is_empty()).then(|| TableDecoder::new(table)))
+                .collect(),
+            _ => vec![Some(TableDecoder::new(codes))],
+        }
+    }
+
+    fn decode_symbols(
+        &self,
+        reader: &mut BitReader,
+        writer: &mut BitWriter,
+        decoders: &[Option<TableDecoder>],
+        count: u32,
+    ) -> Result<(), HuffmanError> {
+        let mut previous = 0u8;
+        for _ in 0..count {
+            // A context without a table can only be reached through a corrupted stream.
+            let decoder = decoders[previous as usize]
+                .as_ref()
+                .ok_or(HuffmanError::InvalidCode)?;
+            let symbol = decoder.decode_symbol(reader)?;
+            if let Symbol::Context(_, byte) = symbol {
+                previous = byte;
+            }
+            self.write_symbol(writer, symbol);
+        }
+
+        Ok(())
+    }
+
+    fn symbol_size(&self) -> usize {
+        match self.symbol {
+            Symbol::TwoBytes(_) => 2,
+            _ => 1,
+        }
+    }
+
+    /// Every block starts with a flag bit that is set when it reuses the previous block's
+    /// table, and clear when a fresh code length table follows.
+    fn write_blocks(&mut self, file_data: &[u8], writer: &mut BitWriter) {
+        let block_size = self.block_size.next_multiple_of(self.symbol_size());
+        writer.write_n_bits(block_size as u32, 32);
+
+        let mut header_bits = writer.bit_len();
+        let mut previous_lengths: Option<HashMap<Symbol, u8>> = None;
+        let mut codes = HashMap::new();
+
+        for block in self.split_blocks(file_data, block_size) {
+            self.clear_counters();
+            self.count_frequencies(block);
+            let lengths = self.compute_code_lengths();
+
+            let mut table = BitWriter::new();
+            self.write_code_length_tables(&mut table, &lengths);
+
+            let fresh_bits = table.bit_len() as u64 + self.payload_bits(&lengths).unwrap_or(0);
+            let reuse = previous_lengths
+                .as_ref()
+                .and_then(|previous_lengths| self.payload_bits(previous_lengths))
+                .is_some_and(|reused_bits| reused_bits <= fresh_bits);
+
+            writer.write_bit(reuse);
+            header_bits += 1;
+
+            if !reuse {
+                self.write_code_length_tables(writer, &lengths);
+                header_bits += table.bit_len();
+
+                codes = self.assign_codes(&lengths);
+                previous_lengths = Some(lengths);
+            }
+
+            self.write_codes(&mut BitReader::new(block), writer, &codes);
+        }
+
+        self.header_size = header_bits.div_ceil(8);
+        self.last_codes = Some(codes);
+    }
+
+    /// Blocks hold `block_size` symbols; for code points that means characters rather than
+    /// bytes, so no block cuts a UTF-8 sequence in half.
+    fn split_blocks<'a>(&self, file_data: &'a [u8], block_size: usize) -> Vec<&'a [u8]> {
+        let Symbol::CodePoint(_) = self.symbol else {
+            return file_data.chunks(block_size).collect();
+        };
+
+        // `compress` only keeps code points for valid UTF-8.
+        let mut rest = std::str::from_utf8(file_data).unwrap_or_default();
+        let mut blocks = Vec::new();
+        while !rest.is_empty() {
+            let end = rest
+                .char_indices()
+                .nth(block_size)
+                .map_or(rest.len(), |(index, _)| index);
+            let (block, tail) = rest.split_at(end);
+            blocks.push(block.as_bytes());
+            rest = tail;
+        }
+
+        blocks
+    }
+
+    fn decompress_blocks(
+        &mut self,
+        reader: &mut BitReader,
+        total_symbols: u32,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        let mut writer = BitWriter::new();
+
+        let symbols_per_block = self.read_block_size(reader)?;
+
+        let mut decoders = Vec::new();
+        self.last_codes = Some(HashMap::new());
+        let mut remaining = total_symbols;
+        while remaining > 0 {
+            let reuse = reader.read_bit().ok_or(HuffmanError::PrematureEnd)?;
+
+            if !reuse {
+                let lengths = self.read_code_length_tables(reader)?;
+                let codes = self.assign_codes(&lengths);
+                decoders = self.build_decoders(&codes);
+                self.last_codes = Some(codes);
+            } else if decoders.is_empty() {
+                // The first block has no previous table to reuse.
+                return Err(HuffmanError::InvalidHeader);
+            }
+
+            let count = remaining.min(symbols_per_block);
+            self.decode_symbols(reader, &mut writer, &decoders, count)?;
+            remaining -= count;
+        }
+        expect_end(reader)?;
+
+        Ok(self.finish_output(writer))
+    }
+
+    /// Reads the block size field and returns how many symbols a full block holds.
+    fn read_block_size(&mut self, reader: &mut BitReader) -> Result<u32, HuffmanError> {
+        self.block_size = read_header_bits(reader, 32)? as usize;
+        if self.block_size < self.symbol_size() {
+            return Err(HuffmanError::InvalidHeader);
+        }
+
+        Ok((self.block_size / self.symbol_size()) as u32)
+    }
+
+    fn write_adaptive_codes(&mut self, reader: &mut BitReader, writer: &mut BitWriter) {
+        let mut adaptive = AdaptiveHuffman::new(self.symbol);
+
+        while let Some(symbol) = self.read_symbol(reader) {
+            adaptive.encode_symbol(symbol, writer);
+        }
+        adaptive.encode_end(writer);
+
+        self.last_codes = Some(adaptive.get_codes());
+    }
+
+    fn decompress_adaptive(&mut self, reader: &mut BitReader) -> Result<Vec<u8>, HuffmanError> {
+        let mut writer = BitWriter::new();
+        let mut adaptive = AdaptiveHuffman::new(self.symbol);
+
+        while let Some(symbol) = adaptive.decode_symbol(reader)? {
+            self.write_symbol(&mut writer, symbol);
+        }
+        expect_end(reader)?;
+
+        self.last_codes = Some(adaptive.get_codes());
+        Ok(self.finish_output(writer))
+    }
+
+    fn finish_output(&self, mut writer: BitWriter) -> Vec<u8> {
+        if let Some(byte) = self.trailing_byte {
+            writer.write_n_bits(byte as u32, 8);
+        }
+
+        writer.finish()
+    }
+
+    /// Parallel blocks are byte-aligned and carry their own tables, so each one is coded on
+    /// its own; the header lists every block's byte length so the decoder can find them up front.
+    fn write_parallel_blocks(&mut self, file_data: &[u8], writer: &mut BitWriter) {
+        let block_size = self.block_size.next_multiple_of(self.symbol_size());
+        let blocks = self.split_blocks(file_data, block_size);
+
+        let mut encoded = run_parallel(&blocks, |block| self.encode_block(block));
+
+        writer.write_n_bits(block_size as u32, 32);
+        writer.write_n_bits(encoded.len() as u32, 32);
+        for block in &encoded {
+            writer.write_n_bits(block.data.len() as u32, 32);
+        }
+        writer.flush();
+
+        let table_bits: usize = encoded.iter().map(|block| block.table_bits).sum();
+        self.header_size = writer.data.len() + table_bits.div_ceil(8);
+
+        for block in &encoded {
+            writer.write_bytes(&block.data);
+        }
+
+        self.last_codes = encoded.pop().map(|block| block.codes);
+    }
+
+    fn encode_block(&self, block: &[u8]) -> EncodedBlock {
+        let mut huffman = self.clone();
+        huffman.clear_counters();
+        huffman.count_frequencies(block);
+
+        let lengths = huffman.compute_code_lengths();
+        let codes = huffman.assign_codes(&lengths);
+
+        let mut writer = BitWriter::new();
+        huffman.write_code_length_tables(&mut writer, &lengths);
+        let table_bits = writer.bit_len();
+        huffman.write_codes(&mut BitReader::new(block), &mut writer, &codes);
+
+        EncodedBlock {
+            data: writer.finish(),
+            table_bits,
+            codes,
+        }
+    }
+
+    fn decompress_parallel_blocks(
+        &mut self,
+        payload: &[u8],
+        reader: &mut BitReader,
+        total_symbols: u32,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        let symbols_per_block = self.read_block_size(reader)?;
+        let block_count = read_header_bits(reader, 32)?;
+
+        let block_lengths = (0..block_count)
+            .map(|_| read_header_bits(reader, 32).map(|length| length as usize))
+            .collect::<Result<Vec<usize>, HuffmanError>>()?;
+
+        let mut offset = reader.align_to_byte();
+        let mut remaining = total_symbols;
+        let mut blocks: Vec<(&[u8], u32)> = Vec::with_capacity(block_lengths.len());
+        for length in block_lengths {
+            let block = payload
+                .get(offset..offset + length)
+                .ok_or(HuffmanError::PrematureEnd)?;
+            let count = remaining.min(symbols_per_block);
+
+            blocks.push((block, count));
+            offset += length;
+            remaining -= count;
+        }
+
+        if remaining > 0 {
+            return Err(HuffmanError::PrematureEnd);
+        }
+        if offset < payload.len() {
+            return Err(HuffmanError::TrailingGarbage);
+        }
+
+        let decoded = run_parallel(&blocks, |&(block, count)| self.decode_block(block, count))
+            .into_iter()
+            .collect::<Result<Vec<Vec<u8>>, HuffmanError>>()?;
+
+        // Like the encoder, report the table of the last block.
+        self.last_codes = blocks
+            .last()
+            .map(|&(block, _)| {
+                self.read_code_length_tables(&mut BitReader::new(block))
+                    .map(|lengths| self.assign_codes(&lengths))
+            })
+            .transpose()?;
+
+        let mut writer = BitWriter::new();
+        for block in &decoded {
+            writer.write_bytes(block);
+        }
+
+        Ok(self.finish_output(writer))
+    }
+
+    fn decode_block(&self, block: &[u8], count: u32) -> Result<Vec<u8>, HuffmanError> {
+        let mut reader = BitReader::new(block);
+        let mut writer = BitWriter::new();
+
+        let lengths = self.read_code_length_tables(&mut reader)?;
+        let decoders = self.build_decoders(&self.assign_codes(&lengths));
+        self.decode_symbols(&mut reader, &mut writer, &decoders, count)?;
+        expect_end(&reader)?;
+
+        Ok(writer.finish())
+    }
+
+    /// Returns the stored symbol total, or zero for adaptive files which have none.
+    fn read_container_header(&mut self, reader: &mut BitReader) -> Result<u32, HuffmanError> {
+        self.symbol = match read_header_bits(reader, 4)? {
+            0 => Symbol::OneByte(0),
+            1 => Symbol::TwoBytes(0),
+            2 => Symbol::Context(0, 0),
+            3 => Symbol::Word(0),
+            4 => Symbol::CodePoint('\0'),
+            _ => return Err(HuffmanError::InvalidHeader),
+        };
+        self.header_format = match (self.symbol, read_header_bits(reader, 4)?) {
+            (Symbol::Context(..), 0 | 2) => return Err(HuffmanError::InvalidHeader),
+            (Symbol::Word(_), format) if format != 1 => return Err(HuffmanError::InvalidHeader),
+            (_, 0) => HeaderFormat::Frequencies,
+            (_, 1) => HeaderFormat::CodeLengths,
+            (_, 2) => HeaderFormat::Adaptive,
+            (_, 3) => HeaderFormat::Blocks,
+            (_, 4) => HeaderFormat::ParallelBlocks,
+            (_, 5) => HeaderFormat::StaticTable,
+            _ => return Err(HuffmanError::InvalidHeader),
+        };
+
+        self.byte_fallback =
+            matches!(self.symbol, Symbol::CodePoint(_)) && read_header_bit(reader)?;
+        if self.byte_fallback {
+            self.symbol = Symbol::OneByte(0);
+        }
+
+        self.trailing_byte = match self.symbol {
+            Symbol::TwoBytes(_) if read_header_bit(reader)? => {
+                Some(read_header_bits(reader, 8)? as u8)
+            }
+            _ => None,
+        };
+
+        match self.header_format {
+            HeaderFormat::Adaptive => Ok(0),
+            _ => read_header_bits(reader, 32),
+        }
+    }
+
+    pub fn read_code_length_tables(
+        &self,
+        reader: &mut BitReader,
+    ) -> Result<HashMap<Symbol, u8>, HuffmanError> {
+        match self.symbol {
+            Symbol::Context(..) => {
+                let contexts =
+                    read_gap_list(reader, CONTEXT_SYMBOL_COUNT_FIELD_BITS, u8::MAX as u32)?;
+
+                let mut lengths = HashMap::new();
+                for previous in contexts {
+                    let table = Symbol::Context(previous as u8, 0);
+                    lengths.extend(Self::read_length_table(reader, table)?);
+                }
+                Ok(lengths)
+            }
+            Symbol::Word(_) => {
+                Self::read_length_table(reader, Symbol::Word(self.words.len() as u32))
+            }
+            _ => Self::read_length_table(reader, self.symbol),
+        }
+    }
+
+    /// Rejects tables that no Huffman tree could have produced, since canonical code
+    /// assignment would otherwise hand out overlapping codes.
+    fn read_length_table(
+        reader: &mut BitReader,
+        table: Symbol,
+    ) -> Result<HashMap<Symbol, u8>, HuffmanError> {
+        let max_length = read_header_bits(reader, CODE_LENGTH_FIELD_BITS)?;
+        let length_bits = canonical::bits_needed(max_length);
+
+        let present = Self::read_present_symbols(reader, table)?;
+
+        let mut lengths = HashMap::with_capacity(present.len());
+        for symbol in present {
+            let length = read_header_bits(reader, length_bits)?;
+            if length == 0 || length > max_length {
+                return Err(HuffmanError::InvalidTree);
+            }
+            lengths.insert(symbol, length as u8);
+        }
+
+        if !canonical::satisfies_kraft(&lengths) {
+            return Err(HuffmanError::InvalidTree);
+        }
+
+        Ok(lengths)
+    }
+
+    fn read_symbol(&self, reader: &mut BitReader) -> Option<Symbol> {
+        match self.symbol {
+            Symbol::OneByte(_) => reader
+                .read_n_bits(8)
+                .map(|byte| Symbol::OneByte(byte as u8)),
+            Symbol::TwoBytes(_) => reader
+                .read_n_bits(16)
+                .map(|value| Symbol::TwoBytes(value as u16)),
+            Symbol::Context(previous, _) => reader
+                .read_n_bits(8)
+                .map(|byte| Symbol::Context(previous, byte as u8)),
+            Symbol::Word(_) => unreachable!("word symbols are always stored as code lengths"),
+            Symbol::CodePoint(_) => {
+                let first = reader.read_n_bits(8)? as u8;
+                let mut bytes = vec![first];
+                for _ in 1..first.leading_ones().max(1) {
+                    bytes.push(reader.read_n_bits(8)? as u8);
+                }
+
+                let c = std::str::from_utf8(&bytes).ok()?.chars().next()?;
+                Some(Symbol::CodePoint(c))
+            }
+        }
+    }
+
+    fn write_symbol(&self, writer: &mut BitWriter, symbol: Symbol) {
+        match symbol {
+            Symbol::OneByte(byte) => {
+                writer.write_n_bits(byte as u32, 8);
+            }
+            Symbol::TwoBytes(symbol_val) => {
+                writer.write_n_bits(symbol_val as u32, 16);
+            }
+            Symbol::Context(_, byte) => {
+                writer.write_n_bits(byte as u32, 8);
+            }
+            Symbol::Word(id) => {
+                writer.write_bytes(self.words[id as usize].as_bytes());
+            }
+            Symbol::CodePoint(c) => {
+                writer.write_bytes(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+        }
+    }
+
+    fn decompress_legacy(&mut self, file_data: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+        let mut reader = BitReader::new(file_data);
+        let mut writer = BitWriter::new();
+
+        let mode_bit = read_header_bit(&mut reader)?;
+        self.symbol = if mode_bit {
+            Symbol::TwoBytes(0)
+        } else {
+            Symbol::OneByte(0)
+        };
+
+        match self.symbol {
+            Symbol::OneByte(_) => self.read_frequency_header_on_one_byte(&mut reader)?,
+            Symbol::TwoBytes(_) => self.read_legacy_frequency_header_on_two_bytes(&mut reader)?,
+            Symbol::Context(..) | Symbol::Word(_) | Symbol::CodePoint(_) => unreachable!(),
+        }
+
+        let total_symbols = self.header_total_symbols()?;
+        self.last_frequencies = Some(self.frequencies());
+
+        let tree = match self.build_huffman_tree() {
+            Some(tree) => tree,
+            None => {
+                self.last_codes = Some(HashMap::new());
+                expect_end(&reader)?;
+                return Ok(writer.finish());
+            }
+        };
+        let codes = tree.path_codes();
+        let decoders = self.build_decoders(&codes);
+        self.last_codes = Some(codes);
+
+        // The legacy encoder gave a lone symbol an empty code, so its count alone says how
+        // many times it repeats and no payload bits follow.
+        if tree.is_leaf() {
+            for _ in 0..total_symbols {
+                self.write_symbol(&mut writer, tree.symbol);
+            }
+            expect_end(&reader)?;
+            return Ok(writer.finish());
+        }
+
+        self.decode_symbols(&mut reader, &mut writer, &decoders, total_symbols)?;
+        expect_end(&reader)?;
+
+        Ok(writer.finish())
+    }
+
+    fn read_frequency_header(&mut self, reader: &mut BitReader) -> Result<(), HuffmanError> {
+        match self.symbol {
+            Symbol::OneByte(_) => self.read_frequency_header_on_one_byte(reader),
+            Symbol::TwoBytes(_) | Symbol::CodePoint(_) => self.read_sparse_frequency_header(reader),
+            Symbol::Context(..) | Symbol::Word(_) => {
+                unreachable!("context and word tables are always stored as code lengths")
+            }
+        }
+    }
+
+    fn read_frequency_header_on_one_byte(
+        &mut self,
+        reader: &mut BitReader,
+    ) -> Result<(), HuffmanError> {
+        self.one_byte_counters.clear();
+
+        let mut encodings = [0u8; 256];
+        for symbol in 0u8..=255u8 {
+            let high_bit = read_header_bit(reader)?;
+            let low_bit = read_header_bit(reader)?;
 
             encodings[symbol as usize] =
                 if high_bit { 0b10 } else { 0b00 } | if low_bit { 0b01 } else { 0b00 };
@@ -335,9 +1556,9 @@ impl Huffman {
 
             let frequency = match encoding {
                 0b00 => 0,
-                0b01 => reader.read_n_bits(8).unwrap_or(0),
-                0b10 => reader.read_n_bits(16).unwrap_or(0),
-                0b11 => reader.read_n_bits(32).unwrap_or(0),
+                0b01 => read_header_bits(reader, 8)?,
+                0b10 => read_header_bits(reader, 16)?,
+                0b11 => read_header_bits(reader, 32)?,
                 _ => unreachable!(),
             };
 
@@ -345,57 +1566,105 @@ impl Huffman {
                 self.one_byte_counters.insert(symbol, frequency);
             }
         }
+
+        Ok(())
+    }
+
+    fn read_sparse_frequency_header(&mut self, reader: &mut BitReader) -> Result<(), HuffmanError> {
+        self.two_bytes_counters.clear();
+        self.code_point_counters.clear();
+
+        let symbols = Self::read_present_symbols(reader, self.symbol)?;
+        let encodings = symbols
+            .iter()
+            .map(|_| read_header_bits(reader, 2))
+            .collect::<Result<Vec<u32>, HuffmanError>>()?;
+
+        for (symbol, encoding) in symbols.into_iter().zip(encodings) {
+            let frequency = match encoding {
+                0b01 => read_header_bits(reader, 8)?,
+                0b10 => read_header_bits(reader, 16)?,
+                0b11 => read_header_bits(reader, 32)?,
+                _ => 0,
+            };
+
+            if frequency == 0 {
+                continue;
+            }
+            match symbol {
+                Symbol::TwoBytes(value) => {
+                    self.two_bytes_counters.insert(value, frequency);
+                }
+                Symbol::CodePoint(c) => {
+                    self.code_point_counters.insert(c, frequency);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
     }
 
-    fn read_frequency_header_on_two_bytes(&mut self, reader: &mut BitReader) {
+    fn read_legacy_frequency_header_on_two_bytes(
+        &mut self,
+        reader: &mut BitReader,
+    ) -> Result<(), HuffmanError> {
         self.two_bytes_counters.clear();
 
         let mut existing_symbols = Vec::new();
         for symbol in 0..65536u32 {
-            let exists = reader.read_bit().unwrap_or(false);
-            if exists {
+            if read_header_bit(reader)? {
                 existing_symbols.push(symbol as u16);
             }
         }
         for &symbol in &existing_symbols {
-            let size_bit = reader.read_bit().unwrap_or(false);
+            let size_bit = read_header_bit(reader)?;
 
             let frequency = if size_bit {
-                reader.read_n_bits(16).unwrap_or(0)
+                read_header_bits(reader, 16)?
             } else {
-                reader.read_n_bits(8).unwrap_or(0)
+                read_header_bits(reader, 8)?
             };
 
             if frequency > 0 {
                 self.two_bytes_counters.insert(symbol, frequency);
             }
         }
+
+        Ok(())
     }
 
-    fn calculate_total_symbols(&self) -> u32 {
-        match self.symbol {
-            Symbol::OneByte(_) => self.one_byte_counters.values().sum(),
-            Symbol::TwoBytes(_) => self.two_bytes_counters.values().sum(),
+    /// Header frequencies are untrusted, so their sum has to be checked before it feeds the
+    /// tree's 32-bit weights.
+    fn header_total_symbols(&self) -> Result<u32, HuffmanError> {
+        self.frequencies()
+            .iter()
+            .try_fold(0u32, |total, &(_, frequency)| total.checked_add(frequency))
+            .ok_or(HuffmanError::InvalidHeader)
+    }
+
+    /// Words are shown as their quoted text rather than their dictionary ID.
+    fn describe_symbol(&self, symbol: Symbol) -> String {
+        match symbol {
+            Symbol::Word(id) => self
+                .words
+                .get(id as usize)
+                .map_or_else(|| symbol.to_string(), |word| format!("{:?}", word)),
+            _ => symbol.to_string(),
         }
     }
 
+    /// Whether code point mode met input that is not UTF-8 and coded it as bytes instead.
+    pub fn used_byte_fallback(&self) -> bool {
+        self.byte_fallback
+    }
+
     pub fn get_codes(&self) -> Option<Vec<(String, String)>> {
         self.last_codes.as_ref().map(|codes| {
             let mut result: Vec<(String, String)> = codes
                 .iter()
                 .map(|(symbol, code)| {
-                    let symbol_str = match symbol {
-                        Symbol::OneByte(byte) => {
-                            if *byte >= 32 && *byte <= 126 {
-                                format!("{} (0x{:02X})", *byte as char, byte)
-                            } else {
-                                format!("byte 0x{:02X}", byte)
-                            }
-                        }
-                        Symbol::TwoBytes(val) => {
-                            format!("bytes 0x{:04X}", val)
-                        }
-                    };
+                    let symbol_str = self.describe_symbol(*symbol);
 
                     let code_str = code
                         .iter()
@@ -411,31 +1680,405 @@ impl Huffman {
         })
     }
 
+    /// Frequencies read from the header on decode, for formats that store them.
+    pub fn get_frequencies(&self) -> Option<Vec<(String, u32)>> {
+        self.last_frequencies.as_ref().map(|frequencies| {
+            frequencies
+                .iter()
+                .map(|(symbol, frequency)| (self.describe_symbol(*symbol), *frequency))
+                .collect()
+        })
+    }
+
     pub fn get_compression_stats(
         &self,
         original_size: usize,
         compressed_size: usize,
     ) -> CompressionStats {
-        let compressed_data_size = compressed_size.saturating_sub(self.header_size);
+        CompressionStats::new(original_size, compressed_size, self.header_size)
+    }
+}
 
-        CompressionStats {
-            original_size,
-            compressed_size,
-            header_size: self.header_size,
-            compressed_data_size,
-            compression_ratio: if original_size > 0 {
-                ((original_size.saturating_sub(compressed_size)) as f64 / original_size as f64)
-                    * 100.0
-            } else {
-                0.0
-            },
-            space_saved: original_size.saturating_sub(compressed_data_size),
-            percentage_saved: if original_size > 0 {
-                ((original_size.saturating_sub(compressed_data_size)) as f64 / original_size as f64)
-                    * 100.0
-            } else {
-                0.0
-            },
+/// Splits `items` into one contiguous chunk per available core and maps each chunk on its
+/// own scoped thread, keeping the results in input order.
+fn run_parallel<T, R, F>(items: &[T], work: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = std::thread::available_parallelism().map_or(1, |count| count.get());
+    let chunk_size = items.len().div_ceil(workers).max(1);
+    let work = &work;
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(work).collect::<Vec<R>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("block worker panicked"))
+            .collect()
+    })
+}
+
+/// Writes an ascending list of values as a count followed by Elias-gamma coded gaps.
+pub fn write_gap_list(writer: &mut BitWriter, values: &[u32], count_bits: u32) {
+    writer.write_n_bits(values.len() as u32, count_bits);
+
+    let mut next_value = 0u32;
+    for &value in values {
+        writer.write_elias_gamma(value - next_value + 1);
+        next_value = value + 1;
+    }
+}
+
+/// Reads a list written by `write_gap_list`, rejecting values above `max_value`.
+pub fn read_gap_list(
+    reader: &mut BitReader,
+    count_bits: u32,
+    max_value: u32,
+) -> Result<Vec<u32>, HuffmanError> {
+    let count = read_header_bits(reader, count_bits)?;
+
+    let mut values = Vec::with_capacity(count as usize);
+    let mut next_value = 0u32;
+    for _ in 0..count {
+        let gap = reader
+            .read_elias_gamma()
+            .ok_or(HuffmanError::TruncatedHeader)?;
+        let value = next_value
+            .checked_add(gap - 1)
+            .filter(|&value| value <= max_value)
+            .ok_or(HuffmanError::InvalidHeader)?;
+        values.push(value);
+        next_value = value + 1;
+    }
+
+    Ok(values)
+}
+
+pub fn read_header_bits(reader: &mut BitReader, count: u32) -> Result<u32, HuffmanError> {
+    reader
+        .read_n_bits(count)
+        .ok_or(HuffmanError::TruncatedHeader)
+}
+
+fn read_header_gamma(reader: &mut BitReader) -> Result<u32, HuffmanError> {
+    reader
+        .read_elias_gamma()
+        .ok_or(HuffmanError::TruncatedHeader)
+}
+
+pub fn read_header_bit(reader: &mut BitReader) -> Result<bool, HuffmanError> {
+    reader.read_bit().ok_or(HuffmanError::TruncatedHeader)
+}
+
+/// Only the zero padding of the final byte may follow the last decoded symbol.
+pub fn expect_end(reader: &BitReader) -> Result<(), HuffmanError> {
+    if reader.bits_remaining() >= 8 {
+        return Err(HuffmanError::TrailingGarbage);
+    }
+
+    Ok(())
+}
+
+/// Places every leaf at the path its code spells out, which takes a complete prefix code
+/// such as a Huffman one. A lone leaf stays the root.
+fn tree_from_codes(leaves: Vec<(Vec<bool>, Node)>, depth: usize) -> Node {
+    if leaves.len() == 1 {
+        return leaves.into_iter().next().unwrap().1;
+    }
+
+    let (left, right): (Vec<_>, Vec<_>) = leaves.into_iter().partition(|(code, _)| !code[depth]);
+    Node::new_parent(
+        tree_from_codes(left, depth + 1),
+        tree_from_codes(right, depth + 1),
+    )
+}
+
+/// Splits a context-keyed map into one map per preceding byte, indexed by that byte.
+fn split_by_context<T: Clone>(map: &HashMap<Symbol, T>) -> Vec<HashMap<Symbol, T>> {
+    let mut tables = vec![HashMap::new(); 256];
+    for (&symbol, value) in map {
+        if let Symbol::Context(previous, _) = symbol {
+            tables[previous as usize].insert(symbol, value.clone());
+        }
+    }
+    tables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTI_MEGABYTE: usize = 4 * 1024 * 1024;
+
+    /// Deterministic xorshift output, one value per byte of input.
+    fn pseudo_random(length: usize) -> impl Iterator<Item = u32> {
+        let mut state = 0x2545_f491_u32;
+        (0..length).map(move |_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        })
+    }
+
+    /// Bytes where `a` makes up about three quarters of the input.
+    fn skewed_input(length: usize) -> Vec<u8> {
+        pseudo_random(length)
+            .map(|value| {
+                if value.is_multiple_of(4) {
+                    (value >> 8) as u8
+                } else {
+                    b'a'
+                }
+            })
+            .collect()
+    }
+
+    fn binary_input(length: usize) -> Vec<u8> {
+        pseudo_random(length)
+            .map(|value| (value >> 8) as u8)
+            .collect()
+    }
+
+    /// Decodes what `encoder` writes for `data` and returns the decoder to inspect its tables.
+    fn round_trip(encoder: &mut Huffman, data: &[u8]) -> Huffman {
+        let encoded = encoder.compress(data).unwrap();
+
+        let mut decoder = Huffman::new(Symbol::OneByte(0));
+        assert_eq!(decoder.decompress(&encoded).unwrap(), data);
+        decoder
+    }
+
+    #[test]
+    fn one_byte_counts_above_16_bits_round_trip() {
+        let data = skewed_input(MULTI_MEGABYTE);
+
+        let decoder = round_trip(&mut Huffman::new(Symbol::OneByte(0)), &data);
+
+        let frequency = decoder.one_byte_counters[&b'a'];
+        assert!(frequency > u16::MAX as u32);
+        assert_eq!(decoder.get_frequency_encoding(frequency), 0b11);
+    }
+
+    #[test]
+    fn two_byte_counts_above_16_bits_round_trip() {
+        let data = vec![b'x'; MULTI_MEGABYTE + 1];
+
+        let decoder = round_trip(&mut Huffman::new(Symbol::TwoBytes(0)), &data);
+
+        assert_eq!(
+            decoder.two_bytes_counters[&u16::from_be_bytes([b'x', b'x'])],
+            (MULTI_MEGABYTE / 2) as u32
+        );
+    }
+
+    #[test]
+    fn scaled_frequency_header_round_trips() {
+        let data = skewed_input(MULTI_MEGABYTE);
+
+        let mut unscaled = Huffman::new(Symbol::OneByte(0));
+        unscaled.compress(&data).unwrap();
+
+        let mut scaled = Huffman::new(Symbol::OneByte(0)).with_frequency_scaling(true);
+        let decoder = round_trip(&mut scaled, &data);
+
+        assert!(decoder
+            .one_byte_counters
+            .values()
+            .all(|&frequency| (1..=MAX_SCALED_FREQUENCY).contains(&frequency)));
+        assert!(scaled.header_size < unscaled.header_size);
+    }
+
+    #[test]
+    fn binary_inputs_round_trip_in_both_modes() {
+        for length in [1000, 1001, 65_537] {
+            let data = binary_input(length);
+
+            round_trip(&mut Huffman::new(Symbol::OneByte(0)), &data);
+            round_trip(&mut Huffman::new(Symbol::TwoBytes(0)), &data);
+        }
+    }
+
+    #[test]
+    fn two_byte_mode_keeps_odd_trailing_byte() {
+        let data = b"abcdefg".to_vec();
+
+        let decoder = round_trip(&mut Huffman::new(Symbol::TwoBytes(0)), &data);
+
+        assert_eq!(decoder.trailing_byte, Some(b'g'));
+    }
+
+    #[test]
+    fn two_byte_mode_round_trips_empty_and_single_byte_input() {
+        round_trip(&mut Huffman::new(Symbol::TwoBytes(0)), &[]);
+        round_trip(&mut Huffman::new(Symbol::TwoBytes(0)), &[0x7f]);
+    }
+
+    #[test]
+    fn frequency_tree_paths_match_written_codes() {
+        let data = skewed_input(10_000);
+
+        for symbol in [Symbol::OneByte(0), Symbol::TwoBytes(0)] {
+            let mut encoder = Huffman::new(symbol);
+            encoder.compress(&data).unwrap();
+
+            let tree = Huffman::new(symbol).frequency_tree(&data).unwrap();
+            assert_eq!(Some(tree.path_codes()), encoder.last_codes);
+        }
+    }
+
+    /// Fibonacci counts make the deepest possible tree, one level per symbol.
+    fn fibonacci_input(symbols: u8) -> Vec<u8> {
+        let (mut count, mut next) = (1, 1);
+        let mut data = Vec::new();
+        for symbol in 0..symbols {
+            data.extend(std::iter::repeat_n(symbol, count));
+            (count, next) = (next, count + next);
+        }
+        data
+    }
+
+    #[test]
+    fn codes_spanning_several_table_levels_decode() {
+        let data = fibonacci_input(24);
+
+        let decoder = round_trip(&mut Huffman::new(Symbol::OneByte(0)), &data);
+        assert!(decoder
+            .last_codes
+            .unwrap()
+            .values()
+            .any(|code| code.len() > 22));
+
+        // Legacy files are headed by a mode bit and the frequency table, and code along the
+        // tree's own paths rather than canonical codes.
+        let mut encoder = Huffman::new(Symbol::OneByte(0));
+        encoder.count_frequencies(&data);
+        let codes = encoder.build_huffman_tree().unwrap().path_codes();
+
+        let mut writer = BitWriter::new();
+        writer.write_bit(false);
+        encoder.write_frequency_header_on_one_byte(&mut writer);
+        encoder.write_frequency_value_on_one_byte(&mut writer);
+        for &byte in &data {
+            for &bit in &codes[&Symbol::OneByte(byte)] {
+                writer.write_bit(bit);
+            }
+        }
+
+        let mut decoder = Huffman::new(Symbol::OneByte(0));
+        assert_eq!(decoder.decompress(&writer.finish()).unwrap(), data);
+    }
+
+    #[test]
+    fn shannon_fano_rejects_max_code_length() {
+        let mut huffman = Huffman::new(Symbol::OneByte(0))
+            .with_shannon_fano(true)
+            .with_max_code_length(Some(8));
+
+        assert_eq!(
+            huffman.compress(b"abracadabra"),
+            Err(HuffmanError::IncompatibleOptions)
+        );
+    }
+
+    #[test]
+    fn max_code_length_below_the_alphabet_is_rejected() {
+        let mut data = skewed_input(4096);
+        data.extend(0..=255u8);
+
+        let mut huffman = Huffman::new(Symbol::OneByte(0)).with_max_code_length(Some(7));
+        assert_eq!(
+            huffman.compress(&data),
+            Err(HuffmanError::IncompatibleOptions)
+        );
+
+        let mut huffman = Huffman::new(Symbol::OneByte(0)).with_max_code_length(Some(8));
+        round_trip(&mut huffman, &data);
+        assert!(huffman
+            .last_codes
+            .unwrap()
+            .values()
+            .all(|code| code.len() <= 8));
+    }
+
+    #[test]
+    fn word_mode_rejects_formats_without_a_dictionary() {
+        for header_format in [
+            HeaderFormat::Adaptive,
+            HeaderFormat::Blocks,
+            HeaderFormat::ParallelBlocks,
+        ] {
+            let mut huffman = Huffman::new(Symbol::Word(0)).with_header_format(header_format);
+
+            assert_eq!(
+                huffman.compress(b"the cat and the hat"),
+                Err(HuffmanError::IncompatibleOptions)
+            );
         }
+
+        round_trip(&mut Huffman::new(Symbol::Word(0)), b"the cat and the hat");
+    }
+
+    #[test]
+    fn context_mode_rejects_adaptive_format() {
+        let mut huffman =
+            Huffman::new(Symbol::Context(0, 0)).with_header_format(HeaderFormat::Adaptive);
+        assert_eq!(
+            huffman.compress(b"abracadabra"),
+            Err(HuffmanError::IncompatibleOptions)
+        );
+
+        let mut huffman = Huffman::new(Symbol::Context(0, 0));
+        round_trip(&mut huffman, b"abracadabra");
+        assert_eq!(huffman.header_format, HeaderFormat::CodeLengths);
+    }
+
+    #[test]
+    fn static_table_rejects_other_modes_and_formats() {
+        let samples = [b"abracadabra".to_vec()];
+        let table =
+            StaticTable::train("sample".to_string(), Symbol::OneByte(0), None, &samples).unwrap();
+
+        let mut huffman = Huffman::new(Symbol::TwoBytes(0)).with_static_table(table.clone());
+        assert_eq!(
+            huffman.compress(b"abracadabra"),
+            Err(HuffmanError::IncompatibleOptions)
+        );
+
+        let mut huffman = Huffman::new(Symbol::OneByte(0))
+            .with_header_format(HeaderFormat::Adaptive)
+            .with_static_table(table.clone());
+        assert_eq!(
+            huffman.compress(b"abracadabra"),
+            Err(HuffmanError::IncompatibleOptions)
+        );
+
+        let mut huffman = Huffman::new(Symbol::OneByte(0)).with_static_table(table);
+        assert!(huffman.compress(b"abracadabra").is_ok());
+        assert_eq!(huffman.header_format, HeaderFormat::StaticTable);
+
+        assert!(matches!(
+            StaticTable::train("words".to_string(), Symbol::Word(0), None, &samples),
+            Err(HuffmanError::IncompatibleOptions)
+        ));
+    }
+
+    #[test]
+    fn code_point_mode_flags_byte_fallback() {
+        let text = "déjà vu 東京".as_bytes();
+        let decoder = round_trip(&mut Huffman::new(Symbol::CodePoint('\0')), text);
+        assert!(!decoder.used_byte_fallback());
+
+        let binary = binary_input(1000);
+        let mut encoder = Huffman::new(Symbol::CodePoint('\0'));
+        let decoder = round_trip(&mut encoder, &binary);
+        assert!(encoder.used_byte_fallback());
+        assert!(decoder.used_byte_fallback());
     }
 }
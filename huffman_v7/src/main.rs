@@ -1,12 +1,24 @@
 use actix_web::{web, App, HttpServer};
 
-use crate::api::{decode_file, encode_file, index};
+use crate::api::{
+    arithmetic_decode, arithmetic_encode, decode_file, encode_file, entropy_analysis, huffman_tree,
+    index, list_tables, train_table, tunstall_decode, tunstall_encode,
+};
 
+mod adaptive;
+mod analysis;
 mod api;
+mod arithmetic;
 mod bit_operations;
+mod decoder;
+mod error;
 mod huffman;
 mod models;
-mod tree;
+mod tables;
+mod tunstall;
+mod words;
+
+use huffman_core::{canonical, tree};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -18,6 +30,14 @@ async fn main() -> std::io::Result<()> {
             .route("/", web::get().to(index))
             .route("/api/encode", web::post().to(encode_file))
             .route("/api/decode", web::post().to(decode_file))
+            .route("/api/huffman/tree", web::post().to(huffman_tree))
+            .route("/api/arithmetic/encode", web::post().to(arithmetic_encode))
+            .route("/api/arithmetic/decode", web::post().to(arithmetic_decode))
+            .route("/api/analysis", web::post().to(entropy_analysis))
+            .route("/api/tables", web::get().to(list_tables))
+            .route("/api/tables/train", web::post().to(train_table))
+            .route("/api/tunstall/encode", web::post().to(tunstall_encode))
+            .route("/api/tunstall/decode", web::post().to(tunstall_decode))
             .service(actix_files::Files::new("/static", "./static").show_files_listing())
     })
     .bind(("127.0.0.1", 8080))?
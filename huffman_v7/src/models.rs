@@ -12,6 +12,8 @@ pub struct EncodeResponse {
     pub compression_ratio: f64,
     pub space_saved: usize,
     pub percentage_saved: f64,
+    /// Set when code point mode was asked for but the input was not UTF-8, so bytes were coded.
+    pub byte_fallback: bool,
     pub codes: Option<Vec<(String, String)>>,
     pub file_data: String,
 }
@@ -24,23 +26,160 @@ pub struct DecodeResponse {
     pub original_size: usize,
     pub decompressed_size: usize,
     pub codes: Option<Vec<(String, String)>>,
+    pub frequencies: Option<Vec<(String, u32)>>,
     pub file_data: String,
 }
 
+#[derive(Serialize)]
+pub struct TreeNode {
+    pub frequency: u32,
+    pub symbol: Option<String>,
+    pub code: Option<String>,
+    pub left: Option<Box<TreeNode>>,
+    pub right: Option<Box<TreeNode>>,
+}
+
+#[derive(Serialize)]
+pub struct TreeResponse {
+    pub success: bool,
+    pub filename: String,
+    pub tree: TreeNode,
+    pub dot: String,
+}
+
+#[derive(Serialize)]
+pub struct AnalysisResponse {
+    pub success: bool,
+    pub filename: String,
+    pub total_bytes: usize,
+    pub total_symbols: u64,
+    pub distinct_symbols: usize,
+    pub order0_entropy: f64,
+    pub order1_entropy: f64,
+    pub order2_entropy: f64,
+    pub symbol_entropy: f64,
+    pub average_code_length: f64,
+    pub redundancy: f64,
+    pub kraft_sum: f64,
+    pub entropy_bound_size: usize,
+    pub huffman_data_size: usize,
+}
+
+#[derive(Serialize)]
+pub struct TrainTableResponse {
+    pub success: bool,
+    pub table_id: String,
+    pub corpus_size: usize,
+    pub symbol_count: usize,
+    pub table_size: usize,
+}
+
+#[derive(Serialize)]
+pub struct TableListResponse {
+    pub success: bool,
+    pub tables: Vec<String>,
+}
+
 #[derive(Serialize)]
 pub struct ErrorResponse {
     pub success: bool,
     pub error: String,
 }
 
+#[derive(Serialize)]
+pub struct HuffmanErrorResponse {
+    pub success: bool,
+    pub error: String,
+    pub kind: String,
+}
+
 #[derive(Deserialize)]
 pub struct EncodingOptions {
     #[serde(default)]
     pub two_bytes: bool,
     #[serde(default)]
+    pub context: bool,
+    #[serde(default)]
+    pub words: bool,
+    #[serde(default)]
+    pub code_points: bool,
+    #[serde(default)]
+    pub show_codes: bool,
+    #[serde(default)]
+    pub code_lengths: bool,
+    #[serde(default)]
+    pub max_code_len: Option<u8>,
+    #[serde(default)]
+    pub adaptive: bool,
+    #[serde(default)]
+    pub scale_frequencies: bool,
+    #[serde(default)]
+    pub block_size: Option<usize>,
+    #[serde(default)]
+    pub parallel: bool,
+    #[serde(default)]
+    pub table: Option<String>,
+    #[serde(default)]
+    pub shannon_fano: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ArithmeticOptions {
+    #[serde(default)]
+    pub two_bytes: bool,
+    #[serde(default)]
+    pub context: bool,
+    #[serde(default)]
+    pub words: bool,
+    #[serde(default)]
+    pub code_points: bool,
+    #[serde(default)]
+    pub adaptive: bool,
+}
+
+#[derive(Deserialize)]
+pub struct TunstallOptions {
+    #[serde(default)]
+    pub two_bytes: bool,
+    #[serde(default)]
+    pub context: bool,
+    #[serde(default)]
+    pub words: bool,
+    #[serde(default)]
+    pub code_points: bool,
+    #[serde(default)]
+    pub code_bits: Option<u8>,
+    #[serde(default)]
     pub show_codes: bool,
 }
 
+#[derive(Deserialize)]
+pub struct TreeOptions {
+    #[serde(default)]
+    pub two_bytes: bool,
+}
+
+#[derive(Deserialize)]
+pub struct AnalysisOptions {
+    #[serde(default)]
+    pub two_bytes: bool,
+    #[serde(default)]
+    pub code_points: bool,
+    #[serde(default)]
+    pub max_code_len: Option<u8>,
+}
+
+#[derive(Deserialize)]
+pub struct TrainTableOptions {
+    pub name: String,
+    #[serde(default)]
+    pub two_bytes: bool,
+    #[serde(default)]
+    pub context: bool,
+    #[serde(default)]
+    pub max_code_len: Option<u8>,
+}
+
 #[derive(Deserialize)]
 pub struct DecodingOptions {
     #[serde(default)]
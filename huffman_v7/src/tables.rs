@@ -0,0 +1,164 @@
+use crate::bit_operations::{BitReader, BitWriter};
+use crate::error::HuffmanError;
+use crate::huffman::{read_header_bits, Huffman};
+use crate::tree::Symbol;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+const HST_MAGIC: &[u8; 3] = b"HST";
+const HST_VERSION: u8 = 1;
+const TABLE_DIRECTORY: &str = "tables";
+const TABLE_EXTENSION: &str = "hst";
+const MAX_TABLE_ID_LENGTH: usize = 64;
+
+/// A code table trained ahead of time, which `.hsa` files refer to by `id` instead of
+/// carrying their own header.
+#[derive(Clone)]
+pub struct StaticTable {
+    pub id: String,
+    pub symbol: Symbol,
+    pub lengths: HashMap<Symbol, u8>,
+}
+
+impl StaticTable {
+    pub fn train(
+        id: String,
+        symbol: Symbol,
+        max_code_length: Option<u8>,
+        samples: &[Vec<u8>],
+    ) -> Result<Self, HuffmanError> {
+        // Word IDs only mean something next to their own dictionary and code points have no
+        // alphabet small enough to smooth over, so neither can be shared.
+        let symbol = match symbol {
+            Symbol::OneByte(_) => Symbol::OneByte(0),
+            Symbol::TwoBytes(_) => Symbol::TwoBytes(0),
+            Symbol::Context(..) => Symbol::Context(0, 0),
+            Symbol::Word(_) | Symbol::CodePoint(_) => {
+                return Err(HuffmanError::IncompatibleOptions)
+            }
+        };
+
+        let mut huffman = Huffman::new(symbol).with_max_code_length(max_code_length);
+        let lengths = huffman.train_code_lengths(samples);
+
+        Ok(Self {
+            id,
+            symbol,
+            lengths,
+        })
+    }
+
+    /// Serialises the table as a small container holding the same code length tables the
+    /// `.hsa` headers use.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = BitWriter::new();
+        for &byte in HST_MAGIC {
+            writer.write_n_bits(byte as u32, 8);
+        }
+        writer.write_n_bits(HST_VERSION as u32, 8);
+
+        let symbol_mode = match self.symbol {
+            Symbol::OneByte(_) => 0,
+            Symbol::TwoBytes(_) => 1,
+            Symbol::Context(..) => 2,
+            Symbol::Word(_) | Symbol::CodePoint(_) => unreachable!(),
+        };
+        writer.write_n_bits(symbol_mode, 8);
+
+        Huffman::new(self.symbol).write_code_length_tables(&mut writer, &self.lengths);
+        writer.finish()
+    }
+
+    pub fn from_bytes(id: String, data: &[u8]) -> Result<Self, HuffmanError> {
+        let is_container = data.len() > HST_MAGIC.len()
+            && data.starts_with(HST_MAGIC)
+            && data[HST_MAGIC.len()] == HST_VERSION;
+        if !is_container {
+            return Err(HuffmanError::InvalidHeader);
+        }
+
+        let mut reader = BitReader::new(&data[HST_MAGIC.len() + 1..]);
+        let symbol = match read_header_bits(&mut reader, 8)? {
+            0 => Symbol::OneByte(0),
+            1 => Symbol::TwoBytes(0),
+            2 => Symbol::Context(0, 0),
+            _ => return Err(HuffmanError::InvalidHeader),
+        };
+        let lengths = Huffman::new(symbol).read_code_length_tables(&mut reader)?;
+
+        Ok(Self {
+            id,
+            symbol,
+            lengths,
+        })
+    }
+
+    pub fn symbol_count(&self) -> usize {
+        self.lengths.len()
+    }
+}
+
+/// Trained tables kept as one `.hst` file per ID in the server's `tables` directory.
+#[derive(Clone)]
+pub struct TableStore {
+    directory: PathBuf,
+}
+
+impl Default for TableStore {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from(TABLE_DIRECTORY),
+        }
+    }
+}
+
+impl TableStore {
+    /// IDs end up in file names and in a length-prefixed header field, so they are kept short
+    /// and limited to ASCII letters, digits, `-` and `_`.
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= MAX_TABLE_ID_LENGTH
+            && id
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{}.{}", id, TABLE_EXTENSION))
+    }
+
+    pub fn save(&self, table: &StaticTable) -> io::Result<usize> {
+        let data = table.to_bytes();
+        std::fs::create_dir_all(&self.directory)?;
+        std::fs::write(self.path(&table.id), &data)?;
+        Ok(data.len())
+    }
+
+    pub fn load(&self, id: &str) -> Result<StaticTable, HuffmanError> {
+        if !Self::is_valid_id(id) {
+            return Err(HuffmanError::UnknownTable);
+        }
+
+        let data = std::fs::read(self.path(id)).map_err(|_| HuffmanError::UnknownTable)?;
+        StaticTable::from_bytes(id.to_string(), &data)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        let mut ids: Vec<String> = std::fs::read_dir(&self.directory)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != TABLE_EXTENSION {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .filter(|id| Self::is_valid_id(id))
+            .collect();
+
+        ids.sort();
+        ids
+    }
+}
@@ -0,0 +1,460 @@
+use crate::bit_operations::{BitReader, BitWriter};
+use crate::canonical;
+use crate::error::HuffmanError;
+use crate::huffman::{expect_end, read_header_bit, read_header_bits, CompressionStats, Huffman};
+use crate::tree::Symbol;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+const HTU_MAGIC: &[u8; 3] = b"HTU";
+const HTU_VERSION: u8 = 1;
+const CODE_BITS_FIELD_BITS: u32 = 5;
+const DEFAULT_CODE_BITS: u32 = 12;
+const MAX_CODE_BITS: u32 = 20;
+
+/// A node of the parse tree. Internal nodes own one child per alphabet symbol, stored as a
+/// contiguous run starting at `first_child`; leaves are the dictionary words.
+struct WordNode {
+    parent: Option<usize>,
+    symbol: usize,
+    first_child: Option<usize>,
+    codeword: u32,
+}
+
+struct Dictionary {
+    nodes: Vec<WordNode>,
+    alphabet_size: usize,
+    leaf_count: usize,
+}
+
+/// A leaf waiting to be expanded, ordered by the probability of its word.
+struct Candidate {
+    probability: f64,
+    node: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.probability
+            .total_cmp(&other.probability)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Dictionary {
+    fn new(alphabet_size: usize) -> Self {
+        Self {
+            nodes: vec![WordNode {
+                parent: None,
+                symbol: 0,
+                first_child: None,
+                codeword: 0,
+            }],
+            alphabet_size,
+            leaf_count: 1,
+        }
+    }
+
+    /// Tunstall's construction: keep expanding the most probable word while the dictionary
+    /// still fits `2^code_bits` codewords. The root is always expanded, so no word is empty.
+    fn build(probabilities: &[f64], code_bits: u32) -> Self {
+        let mut dictionary = Self::new(probabilities.len());
+        let capacity = 1usize << code_bits;
+
+        let mut heap = BinaryHeap::new();
+        heap.push(Candidate {
+            probability: 1.0,
+            node: 0,
+        });
+
+        while let Some(candidate) = heap.pop() {
+            if candidate.node != 0 && dictionary.leaf_count + probabilities.len() - 1 > capacity {
+                break;
+            }
+
+            let first_child = dictionary.expand(candidate.node);
+            for (index, &probability) in probabilities.iter().enumerate() {
+                heap.push(Candidate {
+                    probability: candidate.probability * probability,
+                    node: first_child + index,
+                });
+            }
+        }
+
+        dictionary.number_leaves();
+        dictionary
+    }
+
+    fn expand(&mut self, node: usize) -> usize {
+        let first_child = self.nodes.len();
+        self.nodes[node].first_child = Some(first_child);
+
+        for symbol in 0..self.alphabet_size {
+            self.nodes.push(WordNode {
+                parent: Some(node),
+                symbol,
+                first_child: None,
+                codeword: 0,
+            });
+        }
+
+        self.leaf_count += self.alphabet_size - 1;
+        first_child
+    }
+
+    fn child(&self, node: usize, symbol: usize) -> Option<usize> {
+        self.nodes[node].first_child.map(|first| first + symbol)
+    }
+
+    fn preorder(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            order.push(node);
+            if let Some(first) = self.nodes[node].first_child {
+                stack.extend((first..first + self.alphabet_size).rev());
+            }
+        }
+
+        order
+    }
+
+    /// Leaves get their codewords in preorder, so the tree shape alone fixes the numbering.
+    fn number_leaves(&mut self) -> Vec<usize> {
+        let leaves: Vec<usize> = self
+            .preorder()
+            .into_iter()
+            .filter(|&node| self.nodes[node].first_child.is_none())
+            .collect();
+
+        for (codeword, &node) in leaves.iter().enumerate() {
+            self.nodes[node].codeword = codeword as u32;
+        }
+        leaves
+    }
+
+    /// One bit per node in preorder: set for internal nodes, clear for words.
+    fn write_shape(&self, writer: &mut BitWriter) {
+        for node in self.preorder() {
+            writer.write_bit(self.nodes[node].first_child.is_some());
+        }
+    }
+
+    fn read_shape(
+        reader: &mut BitReader,
+        alphabet_size: usize,
+        code_bits: u32,
+    ) -> Result<Self, HuffmanError> {
+        let mut dictionary = Self::new(alphabet_size);
+        let capacity = 1usize << code_bits;
+
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            if !read_header_bit(reader)? {
+                continue;
+            }
+
+            if dictionary.leaf_count + alphabet_size - 1 > capacity {
+                return Err(HuffmanError::InvalidTree);
+            }
+            let first = dictionary.expand(node);
+            stack.extend((first..first + alphabet_size).rev());
+        }
+
+        // An empty word would never consume input.
+        if dictionary.nodes[0].first_child.is_none() {
+            return Err(HuffmanError::InvalidTree);
+        }
+
+        Ok(dictionary)
+    }
+
+    fn word(&self, mut node: usize) -> Vec<usize> {
+        let mut word = Vec::new();
+        while let Some(parent) = self.nodes[node].parent {
+            word.push(self.nodes[node].symbol);
+            node = parent;
+        }
+
+        word.reverse();
+        word
+    }
+}
+
+/// Variable-to-fixed coding: the input is parsed into words of a Tunstall dictionary, and
+/// every word is written as a `code_bits` wide index. Stored in its own `.htu` container.
+pub struct Tunstall {
+    symbol: Symbol,
+    code_bits: u32,
+    trailing_byte: Option<u8>,
+    header_size: usize,
+    last_codes: Option<Vec<(String, String)>>,
+}
+
+impl Tunstall {
+    /// Dictionary words are spelled in bytes or byte pairs. Every letter of the alphabet has
+    /// to be a one-letter word, so `compress` rejects words and code points, which have no
+    /// small fixed alphabet, and context pairs, which would need a dictionary per preceding byte.
+    pub fn new(symbol: Symbol) -> Self {
+        Self {
+            symbol,
+            code_bits: DEFAULT_CODE_BITS,
+            trailing_byte: None,
+            header_size: 0,
+            last_codes: None,
+        }
+    }
+
+    /// Raised when needed so that every alphabet symbol still gets a one-symbol word.
+    pub fn with_code_bits(mut self, code_bits: Option<u8>) -> Self {
+        self.code_bits = code_bits
+            .map_or(DEFAULT_CODE_BITS, |bits| bits as u32)
+            .clamp(1, MAX_CODE_BITS);
+        self
+    }
+
+    fn symbol_bits(&self) -> u32 {
+        match self.symbol {
+            Symbol::TwoBytes(_) => 16,
+            _ => 8,
+        }
+    }
+
+    fn symbol_value(symbol: Symbol) -> u32 {
+        match symbol {
+            Symbol::OneByte(byte) => byte as u32,
+            Symbol::TwoBytes(value) => value as u32,
+            Symbol::Context(..) | Symbol::Word(_) | Symbol::CodePoint(_) => unreachable!(),
+        }
+    }
+
+    pub fn compress(&mut self, file_data: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+        if file_data.len() > u32::MAX as usize {
+            return Err(HuffmanError::InputTooLarge);
+        }
+
+        if !matches!(self.symbol, Symbol::OneByte(_) | Symbol::TwoBytes(_)) {
+            return Err(HuffmanError::IncompatibleOptions);
+        }
+
+        let mut writer = BitWriter::new();
+
+        self.trailing_byte = match self.symbol {
+            Symbol::TwoBytes(_) if file_data.len() % 2 == 1 => file_data.last().copied(),
+            _ => None,
+        };
+
+        let mut huffman = Huffman::new(self.symbol);
+        huffman.count_frequencies(file_data);
+        let frequencies = huffman.frequencies();
+        let alphabet: Vec<Symbol> = frequencies.iter().map(|&(symbol, _)| symbol).collect();
+        let total: u64 = frequencies.iter().map(|&(_, count)| count as u64).sum();
+
+        self.code_bits = self.code_bits.max(canonical::bits_needed(
+            alphabet.len().saturating_sub(1) as u32,
+        ));
+
+        self.write_container_header(&mut writer);
+        writer.write_n_bits(self.code_bits, CODE_BITS_FIELD_BITS);
+        writer.write_n_bits(total as u32, 32);
+        huffman.write_present_symbols(&mut writer, &alphabet);
+
+        // A single-symbol alphabet is fully described by the symbol total.
+        if alphabet.len() < 2 {
+            self.header_size = writer.bit_len().div_ceil(8);
+            self.last_codes = Some(Vec::new());
+            return Ok(writer.finish());
+        }
+
+        let probabilities: Vec<f64> = frequencies
+            .iter()
+            .map(|&(_, count)| count as f64 / total as f64)
+            .collect();
+        let dictionary = Dictionary::build(&probabilities, self.code_bits);
+        dictionary.write_shape(&mut writer);
+        self.header_size = writer.bit_len().div_ceil(8);
+
+        let indices: HashMap<u32, usize> = alphabet
+            .iter()
+            .enumerate()
+            .map(|(index, &symbol)| (Self::symbol_value(symbol), index))
+            .collect();
+
+        let mut node = 0;
+        let mut reader = BitReader::new(file_data);
+        while let Some(value) = reader.read_n_bits(self.symbol_bits()) {
+            node = dictionary.child(node, indices[&value]).unwrap_or(0);
+            if dictionary.nodes[node].first_child.is_none() {
+                writer.write_n_bits(dictionary.nodes[node].codeword, self.code_bits);
+                node = 0;
+            }
+        }
+
+        // The input ended inside a word; any word below it works since the decoder stops
+        // at the symbol total.
+        if node != 0 {
+            while let Some(first) = dictionary.nodes[node].first_child {
+                node = first;
+            }
+            writer.write_n_bits(dictionary.nodes[node].codeword, self.code_bits);
+        }
+
+        self.last_codes = Some(self.describe_codes(&dictionary, &alphabet));
+        Ok(writer.finish())
+    }
+
+    fn write_container_header(&self, writer: &mut BitWriter) {
+        for &byte in HTU_MAGIC {
+            writer.write_n_bits(byte as u32, 8);
+        }
+        writer.write_n_bits(HTU_VERSION as u32, 8);
+
+        let symbol_mode = match self.symbol {
+            Symbol::TwoBytes(_) => 1,
+            _ => 0,
+        };
+        writer.write_n_bits(symbol_mode, 8);
+
+        if let Symbol::TwoBytes(_) = self.symbol {
+            writer.write_bit(self.trailing_byte.is_some());
+            if let Some(byte) = self.trailing_byte {
+                writer.write_n_bits(byte as u32, 8);
+            }
+        }
+    }
+
+    pub fn decompress(&mut self, file_data: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+        let is_container = file_data.len() > HTU_MAGIC.len()
+            && file_data.starts_with(HTU_MAGIC)
+            && file_data[HTU_MAGIC.len()] == HTU_VERSION;
+        if !is_container {
+            return Err(HuffmanError::InvalidHeader);
+        }
+
+        let mut reader = BitReader::new(&file_data[HTU_MAGIC.len() + 1..]);
+        let mut writer = BitWriter::new();
+
+        self.read_container_header(&mut reader)?;
+        self.code_bits = read_header_bits(&mut reader, CODE_BITS_FIELD_BITS)?;
+        let total_symbols = read_header_bits(&mut reader, 32)?;
+        let alphabet = Huffman::read_present_symbols(&mut reader, self.symbol)?;
+
+        if self.code_bits == 0
+            || self.code_bits > MAX_CODE_BITS
+            || alphabet.len() > 1 << self.code_bits
+            || (total_symbols > 0 && alphabet.is_empty())
+        {
+            return Err(HuffmanError::InvalidHeader);
+        }
+
+        if alphabet.len() == 1 {
+            for _ in 0..total_symbols {
+                writer.write_n_bits(Self::symbol_value(alphabet[0]), self.symbol_bits());
+            }
+        } else if !alphabet.is_empty() {
+            let mut dictionary =
+                Dictionary::read_shape(&mut reader, alphabet.len(), self.code_bits)?;
+            let leaves = dictionary.number_leaves();
+
+            let mut remaining = total_symbols as usize;
+            while remaining > 0 {
+                let codeword = reader
+                    .read_n_bits(self.code_bits)
+                    .ok_or(HuffmanError::PrematureEnd)? as usize;
+                let node = *leaves.get(codeword).ok_or(HuffmanError::InvalidCode)?;
+
+                for index in dictionary.word(node).into_iter().take(remaining) {
+                    let value = Self::symbol_value(alphabet[index]);
+                    writer.write_n_bits(value, self.symbol_bits());
+                    remaining -= 1;
+                }
+            }
+
+            self.last_codes = Some(self.describe_codes(&dictionary, &alphabet));
+        }
+        expect_end(&reader)?;
+
+        if let Some(byte) = self.trailing_byte {
+            writer.write_n_bits(byte as u32, 8);
+        }
+
+        Ok(writer.finish())
+    }
+
+    fn read_container_header(&mut self, reader: &mut BitReader) -> Result<(), HuffmanError> {
+        self.symbol = match read_header_bits(reader, 8)? {
+            0 => Symbol::OneByte(0),
+            1 => Symbol::TwoBytes(0),
+            _ => return Err(HuffmanError::InvalidHeader),
+        };
+
+        self.trailing_byte = match self.symbol {
+            Symbol::TwoBytes(_) if read_header_bit(reader)? => {
+                Some(read_header_bits(reader, 8)? as u8)
+            }
+            _ => None,
+        };
+
+        Ok(())
+    }
+
+    /// Pairs every dictionary word with its codeword, one-byte words shown as escaped text.
+    fn describe_codes(
+        &self,
+        dictionary: &Dictionary,
+        alphabet: &[Symbol],
+    ) -> Vec<(String, String)> {
+        let mut codes: Vec<(String, String)> = (0..dictionary.nodes.len())
+            .filter(|&node| dictionary.nodes[node].first_child.is_none())
+            .map(|node| {
+                let word: String = dictionary
+                    .word(node)
+                    .into_iter()
+                    .map(|index| match alphabet[index] {
+                        Symbol::TwoBytes(value) => format!("[{:04X}]", value),
+                        Symbol::OneByte(byte) => std::ascii::escape_default(byte).to_string(),
+                        Symbol::Context(..) | Symbol::Word(_) | Symbol::CodePoint(_) => {
+                            unreachable!()
+                        }
+                    })
+                    .collect();
+                let codeword = format!(
+                    "{:0width$b}",
+                    dictionary.nodes[node].codeword,
+                    width = self.code_bits as usize
+                );
+                (word, codeword)
+            })
+            .collect();
+
+        codes.sort_by(|a, b| a.1.cmp(&b.1));
+        codes
+    }
+
+    pub fn get_codes(&self) -> Option<Vec<(String, String)>> {
+        self.last_codes.clone()
+    }
+
+    pub fn get_compression_stats(
+        &self,
+        original_size: usize,
+        compressed_size: usize,
+    ) -> CompressionStats {
+        CompressionStats::new(original_size, compressed_size, self.header_size)
+    }
+}
@@ -0,0 +1,55 @@
+/// Splits UTF-8 text into the tokens of the word symbol mode. Runs of letters and digits form
+/// one word, runs of whitespace form one token, and every other character stands alone.
+/// CJK scripts are written without spaces, so each of their characters is its own word.
+pub fn tokenize(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let class = TokenClass::of(first);
+
+        let length = match class {
+            TokenClass::Word | TokenClass::Whitespace => rest
+                .char_indices()
+                .find(|&(_, c)| TokenClass::of(c) != class)
+                .map_or(rest.len(), |(index, _)| index),
+            TokenClass::Single => first.len_utf8(),
+        };
+
+        let (token, tail) = rest.split_at(length);
+        rest = tail;
+        Some(token)
+    })
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum TokenClass {
+    Word,
+    Whitespace,
+    Single,
+}
+
+impl TokenClass {
+    fn of(c: char) -> Self {
+        if is_cjk(c) {
+            TokenClass::Single
+        } else if c.is_alphanumeric() {
+            TokenClass::Word
+        } else if c.is_whitespace() {
+            TokenClass::Whitespace
+        } else {
+            TokenClass::Single
+        }
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF // Hiragana and Katakana
+        | 0x3400..=0x4DBF // CJK Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF // Hangul syllables
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+        | 0x20000..=0x2FA1F // Supplementary ideographic planes
+    )
+}
@@ -7,8 +7,27 @@ const encodeFileNameDisplay = document.getElementById("encode-file-name");
 const encodeButton = document.getElementById("encode-button");
 const encodeShowCodes = document.getElementById("encode-show-codes");
 const encodeTwoBytes = document.getElementById("encode-two-bytes");
+const encodeContext = document.getElementById("encode-context");
+const encodeWords = document.getElementById("encode-words");
+const encodeCodePoints = document.getElementById("encode-code-points");
+const encodeCodeLengths = document.getElementById("encode-code-lengths");
+const encodeAdaptive = document.getElementById("encode-adaptive");
+const encodeScaleFrequencies = document.getElementById(
+  "encode-scale-frequencies"
+);
+const encodeBlocks = document.getElementById("encode-blocks");
+const encodeParallel = document.getElementById("encode-parallel");
+const encodeTable = document.getElementById("encode-table");
+const encodeShannonFano = document.getElementById("encode-shannon-fano");
 const encodeResults = document.getElementById("encode-results");
 const encodeCodesContainer = document.getElementById("encode-codes-container");
+const arithmeticButton = document.getElementById("arithmetic-button");
+const trainTableButton = document.getElementById("train-table-button");
+const tunstallButton = document.getElementById("tunstall-button");
+const treeButton = document.getElementById("tree-button");
+const treeContainer = document.getElementById("tree-container");
+const analysisButton = document.getElementById("analysis-button");
+const analysisResults = document.getElementById("analysis-results");
 
 encodeFileInput.addEventListener("change", (e) => {
   const file = e.target.files[0];
@@ -17,26 +36,63 @@ encodeFileInput.addEventListener("change", (e) => {
     encodeFileName = file.name;
     encodeFileNameDisplay.textContent = file.name;
     encodeButton.disabled = false;
+    arithmeticButton.disabled = false;
+    trainTableButton.disabled = false;
+    tunstallButton.disabled = false;
+    treeButton.disabled = false;
+    analysisButton.disabled = false;
 
     encodeResults.style.display = "none";
     encodeCodesContainer.style.display = "none";
+    treeContainer.style.display = "none";
+    analysisResults.style.display = "none";
   }
 });
 
-encodeButton.addEventListener("click", async () => {
+encodeButton.addEventListener("click", () => {
+  const showCodes = encodeShowCodes.checked;
+  const twoBytes = encodeTwoBytes.checked;
+  const context = encodeContext.checked;
+  const words = encodeWords.checked;
+  const codePoints = encodeCodePoints.checked;
+  const codeLengths = encodeCodeLengths.checked;
+  const adaptive = encodeAdaptive.checked;
+  const scaleFrequencies = encodeScaleFrequencies.checked;
+  const blockSize = encodeBlocks.checked ? "&block_size=65536" : "";
+  const parallel = encodeParallel.checked;
+  const shannonFano = encodeShannonFano.checked;
+  const table = encodeTable.value.trim()
+    ? `&table=${encodeURIComponent(encodeTable.value.trim())}`
+    : "";
+
+  encodeWith(
+    `/api/encode?show_codes=${showCodes}&two_bytes=${twoBytes}&context=${context}&words=${words}&code_points=${codePoints}&code_lengths=${codeLengths}&adaptive=${adaptive}&scale_frequencies=${scaleFrequencies}&parallel=${parallel}&shannon_fano=${shannonFano}${blockSize}${table}`,
+    showCodes
+  );
+});
+
+trainTableButton.addEventListener("click", async () => {
   if (!encodeFile) return;
 
+  const name = encodeTable.value.trim();
+  if (!name) {
+    alert("Enter a name for the static table first");
+    return;
+  }
+
   showLoading(true);
 
   const formData = new FormData();
   formData.append("file", encodeFile);
 
-  const showCodes = encodeShowCodes.checked;
   const twoBytes = encodeTwoBytes.checked;
+  const context = encodeContext.checked;
 
   try {
     const response = await fetch(
-      `/api/encode?show_codes=${showCodes}&two_bytes=${twoBytes}`,
+      `/api/tables/train?name=${encodeURIComponent(
+        name
+      )}&two_bytes=${twoBytes}&context=${context}`,
       {
         method: "POST",
         body: formData,
@@ -45,6 +101,79 @@ encodeButton.addEventListener("click", async () => {
 
     const result = await response.json();
 
+    if (result.success) {
+      alert(
+        `Trained table "${result.table_id}" from ${formatBytes(
+          result.corpus_size
+        )} (${formatBytes(result.table_size)} stored)`
+      );
+      loadTables();
+    } else {
+      alert("Error: " + (result.error || result.message));
+    }
+  } catch (error) {
+    alert("Error training table: " + error.message);
+    console.error(error);
+  } finally {
+    showLoading(false);
+  }
+});
+
+async function loadTables() {
+  try {
+    const response = await fetch("/api/tables");
+    const result = await response.json();
+
+    const list = document.getElementById("encode-table-list");
+    list.innerHTML = "";
+    result.tables.forEach((table) => {
+      const option = document.createElement("option");
+      option.value = table;
+      list.appendChild(option);
+    });
+  } catch (error) {
+    console.error(error);
+  }
+}
+
+loadTables();
+
+arithmeticButton.addEventListener("click", () => {
+  const twoBytes = encodeTwoBytes.checked;
+  const adaptive = encodeAdaptive.checked;
+
+  encodeWith(
+    `/api/arithmetic/encode?two_bytes=${twoBytes}&adaptive=${adaptive}`,
+    false
+  );
+});
+
+tunstallButton.addEventListener("click", () => {
+  const showCodes = encodeShowCodes.checked;
+  const twoBytes = encodeTwoBytes.checked;
+
+  encodeWith(
+    `/api/tunstall/encode?show_codes=${showCodes}&two_bytes=${twoBytes}`,
+    showCodes
+  );
+});
+
+async function encodeWith(url, showCodes) {
+  if (!encodeFile) return;
+
+  showLoading(true);
+
+  const formData = new FormData();
+  formData.append("file", encodeFile);
+
+  try {
+    const response = await fetch(url, {
+      method: "POST",
+      body: formData,
+    });
+
+    const result = await response.json();
+
     if (result.success) {
       document.getElementById("encode-original-size").textContent = formatBytes(
         result.original_size
@@ -62,6 +191,8 @@ encodeButton.addEventListener("click", async () => {
       document.getElementById("encode-saved").textContent = `${formatBytes(
         result.space_saved
       )} (${result.percentage_saved.toFixed(2)}%)`;
+      document.getElementById("encode-fallback").style.display =
+        result.byte_fallback ? "flex" : "none";
 
       encodeResults.style.display = "block";
 
@@ -83,8 +214,116 @@ encodeButton.addEventListener("click", async () => {
   } finally {
     showLoading(false);
   }
+}
+
+treeButton.addEventListener("click", async () => {
+  if (!encodeFile) return;
+
+  showLoading(true);
+
+  const formData = new FormData();
+  formData.append("file", encodeFile);
+
+  const twoBytes = encodeTwoBytes.checked;
+
+  try {
+    const response = await fetch(`/api/huffman/tree?two_bytes=${twoBytes}`, {
+      method: "POST",
+      body: formData,
+    });
+
+    const result = await response.json();
+
+    if (result.success) {
+      const treeView = document.getElementById("tree-view");
+      treeView.innerHTML = "";
+      treeView.appendChild(renderTreeNode(result.tree));
+      document.getElementById("tree-dot").textContent = result.dot;
+      treeContainer.style.display = "block";
+    } else {
+      alert("Error: " + (result.error || result.message));
+    }
+  } catch (error) {
+    alert("Error building tree: " + error.message);
+    console.error(error);
+  } finally {
+    showLoading(false);
+  }
 });
 
+analysisButton.addEventListener("click", async () => {
+  if (!encodeFile) return;
+
+  showLoading(true);
+
+  const formData = new FormData();
+  formData.append("file", encodeFile);
+
+  const twoBytes = encodeTwoBytes.checked;
+  const codePoints = encodeCodePoints.checked;
+
+  try {
+    const response = await fetch(
+      `/api/analysis?two_bytes=${twoBytes}&code_points=${codePoints}`,
+      {
+        method: "POST",
+        body: formData,
+      }
+    );
+
+    const result = await response.json();
+
+    if (result.success) {
+      document.getElementById("analysis-order0").textContent =
+        result.order0_entropy.toFixed(4) + " bits/byte";
+      document.getElementById("analysis-order1").textContent =
+        result.order1_entropy.toFixed(4) + " bits/byte";
+      document.getElementById("analysis-order2").textContent =
+        result.order2_entropy.toFixed(4) + " bits/byte";
+      document.getElementById("analysis-average-length").textContent =
+        result.average_code_length.toFixed(4) + " bits/symbol";
+      document.getElementById("analysis-redundancy").textContent =
+        result.redundancy.toFixed(4) + " bits/symbol";
+      document.getElementById("analysis-kraft").textContent =
+        result.kraft_sum.toFixed(4);
+      document.getElementById("analysis-bound").textContent = formatBytes(
+        result.entropy_bound_size
+      );
+      document.getElementById("analysis-huffman-size").textContent =
+        formatBytes(result.huffman_data_size);
+
+      analysisResults.style.display = "block";
+    } else {
+      alert("Error: " + (result.error || result.message));
+    }
+  } catch (error) {
+    alert("Error analyzing file: " + error.message);
+    console.error(error);
+  } finally {
+    showLoading(false);
+  }
+});
+
+function renderTreeNode(node) {
+  const list = document.createElement("ul");
+  const item = document.createElement("li");
+
+  if (node.symbol !== null) {
+    item.className = "tree-leaf";
+    item.textContent = `${node.symbol} — ${node.frequency} — ${node.code}`;
+  } else {
+    item.textContent = node.frequency;
+    [node.left, node.right].forEach((child) => {
+      if (child) {
+        item.appendChild(renderTreeNode(child));
+      }
+    });
+  }
+
+  list.appendChild(item);
+  return list;
+}
+
 document.getElementById("download-encoded").addEventListener("click", () => {
   if (encodeFileData) {
     downloadFile(encodeFileData, encodeFileName);
@@ -123,9 +362,15 @@ decodeButton.addEventListener("click", async () => {
   formData.append("file", decodeFile);
 
   const showCodes = decodeShowCodes.checked;
+  let url = `/api/decode?show_codes=${showCodes}`;
+  if (/\.hac$/i.test(decodeFile.name)) {
+    url = "/api/arithmetic/decode";
+  } else if (/\.htu$/i.test(decodeFile.name)) {
+    url = `/api/tunstall/decode?show_codes=${showCodes}`;
+  }
 
   try {
-    const response = await fetch(`/api/decode?show_codes=${showCodes}`, {
+    const response = await fetch(url, {
       method: "POST",
       body: formData,
     });
@@ -147,6 +392,19 @@ decodeButton.addEventListener("click", async () => {
       if (showCodes && result.codes) {
         displayCodes("decode-codes", result.codes);
         decodeCodesContainer.style.display = "block";
+
+        const frequenciesSection = document.getElementById(
+          "decode-frequencies-section"
+        );
+        if (result.frequencies) {
+          displayCodes(
+            "decode-frequencies",
+            result.frequencies.map(([symbol, count]) => [symbol, String(count)])
+          );
+          frequenciesSection.style.display = "block";
+        } else {
+          frequenciesSection.style.display = "none";
+        }
       } else {
         decodeCodesContainer.style.display = "none";
       }
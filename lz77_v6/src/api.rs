@@ -1,20 +1,53 @@
 use actix_web::{HttpResponse, Result, web};
 
 use crate::lz77::LZ77;
-use crate::models::{DecodeRequest, DecodeResponse, EncodeRequest, EncodeResponse};
+use crate::models::{
+    DecodeRequest, DecodeResponse, EncodeRequest, EncodeResponse, ErrorResponse, TokenFormat,
+};
 
 pub async fn encode_file(req: web::Json<EncodeRequest>) -> Result<HttpResponse> {
-    let mut lz = LZ77::new(Some(req.offset_bits), Some(req.length_bits));
-    let encoded_bytes = lz.encode(&req.file_data);
+    let mut lz = LZ77::new(Some(req.offset_bits), Some(req.length_bits))
+        .with_chain_depth(req.chain_depth)
+        .with_lazy_depth(req.lazy_depth)
+        .with_optimal_parsing(req.optimal_parsing)
+        .with_token_format(req.token_format)
+        .with_min_match_length(req.min_match_length);
+    let encoded_bytes = match lz.encode(&req.file_data) {
+        Ok(encoded_bytes) => encoded_bytes,
+        Err(error) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: error.to_string(),
+                kind: error.kind().to_string(),
+            }));
+        }
+    };
+
+    // Same options as above, which the encoder has already accepted.
+    let greedy_compressed_size = req.optimal_parsing.then(|| {
+        LZ77::new(Some(req.offset_bits), Some(req.length_bits))
+            .with_chain_depth(req.chain_depth)
+            .with_token_format(req.token_format)
+            .with_min_match_length(req.min_match_length)
+            .encode(&req.file_data)
+            .map_or(0, |encoded| encoded.len())
+    });
 
     let original_size = req.file_data.len();
     let compressed_size = encoded_bytes.len();
     let compression_ratio = LZ77::calculate_compression_ratio(original_size, compressed_size);
 
-    let encoded_filename = format!(
-        "{}.o{}l{}.lz77",
-        req.filename, req.offset_bits, req.length_bits
-    );
+    // DEFLATE has no length bits, so its names only carry the offset bits.
+    let encoded_filename = match req.token_format {
+        TokenFormat::Triples => format!(
+            "{}.o{}l{}.lz77",
+            req.filename, req.offset_bits, req.length_bits
+        ),
+        TokenFormat::Lzss => format!(
+            "{}.o{}l{}.lzss.lz77",
+            req.filename, req.offset_bits, req.length_bits
+        ),
+        TokenFormat::Deflate => format!("{}.o{}.deflate.lz77", req.filename, req.offset_bits),
+    };
 
     let tokens = lz.get_tokens();
 
@@ -24,6 +57,7 @@ pub async fn encode_file(req: web::Json<EncodeRequest>) -> Result<HttpResponse>
         original_size,
         compressed_size,
         compression_ratio,
+        greedy_compressed_size,
         tokens: Some(tokens),
     };
 
@@ -32,7 +66,15 @@ pub async fn encode_file(req: web::Json<EncodeRequest>) -> Result<HttpResponse>
 
 pub async fn decode_file(req: web::Json<DecodeRequest>) -> Result<HttpResponse> {
     let mut lz = LZ77::new(None, None);
-    let decoded_bytes = lz.decode(&req.file_data);
+    let decoded_bytes = match lz.decode(&req.file_data) {
+        Ok(decoded_bytes) => decoded_bytes,
+        Err(error) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: error.to_string(),
+                kind: error.kind().to_string(),
+            }));
+        }
+    };
 
     let original_compressed_size = req.file_data.len();
     let decompressed_size = decoded_bytes.len();
@@ -50,10 +92,10 @@ pub async fn decode_file(req: web::Json<DecodeRequest>) -> Result<HttpResponse>
 }
 
 fn extract_original_filename(encoded_filename: &str) -> String {
-    if let Some(pos) = encoded_filename.rfind(".o") {
-        if encoded_filename.ends_with(".lz77") {
-            return encoded_filename[..pos].to_string();
-        }
+    if let Some(pos) = encoded_filename.rfind(".o")
+        && encoded_filename.ends_with(".lz77")
+    {
+        return encoded_filename[..pos].to_string();
     }
 
     encoded_filename
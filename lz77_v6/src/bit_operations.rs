@@ -38,6 +38,10 @@ impl<'a> BitReader<'a> {
         }
         Some(result)
     }
+
+    pub fn bits_remaining(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.byte_position * 8 + self.bit_position as usize)
+    }
 }
 
 pub struct BitWriter {
@@ -0,0 +1,287 @@
+use crate::bit_operations::{BitReader, BitWriter};
+use crate::error::LZ77Error;
+use crate::models::Token;
+use huffman_core::canonical::{
+    assign_canonical_codes, length_limited_code_lengths, satisfies_kraft,
+};
+use std::collections::HashMap;
+
+pub const MIN_MATCH_LENGTH: usize = 3;
+pub const MAX_MATCH_LENGTH: usize = 258;
+
+const END_OF_BLOCK: u16 = 256;
+const FIRST_LENGTH_SYMBOL: u16 = 257;
+const MAX_CODE_LENGTH: u8 = 15;
+const CODE_LENGTH_BITS: u32 = 4;
+const LITERAL_LENGTH_COUNT_BITS: u32 = 9;
+const DISTANCE_COUNT_BITS: u32 = 5;
+
+/// The shortest length of every length symbol from 257 on, and how many extra bits pick a
+/// length inside its bucket.
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Returns the bucket `value` falls into, its number of extra bits and the offset inside it.
+fn bucket(bases: &[u16], extra_bits: &[u8], value: usize) -> (usize, u8, u32) {
+    let index = bases.partition_point(|&base| base as usize <= value) - 1;
+    (
+        index,
+        extra_bits[index],
+        (value - bases[index] as usize) as u32,
+    )
+}
+
+/// Literals are symbols 0-255, the end of the block 256 and match lengths 257 on.
+fn literal_length_symbol(token: &Token) -> (u16, u8, u32) {
+    match token.next_char {
+        Some(byte) => (byte as u16, 0, 0),
+        None => {
+            let (index, extra_bits, extra) =
+                bucket(&LENGTH_BASES, &LENGTH_EXTRA_BITS, token.match_length);
+            (FIRST_LENGTH_SYMBOL + index as u16, extra_bits, extra)
+        }
+    }
+}
+
+fn distance_symbol(token: &Token) -> (u16, u8, u32) {
+    let (index, extra_bits, extra) = bucket(&DISTANCE_BASES, &DISTANCE_EXTRA_BITS, token.offset);
+    (index as u16, extra_bits, extra)
+}
+
+/// Size of a token under DEFLATE's fixed codes, which stands in for the real ones while
+/// parsing since those depend on the tokens that get picked.
+pub fn estimated_token_bits(token: &Token) -> u64 {
+    let (symbol, length_extra_bits, _) = literal_length_symbol(token);
+    let symbol_bits = match symbol {
+        0..=143 | 280.. => 8,
+        144..=255 => 9,
+        _ => 7,
+    };
+
+    if token.next_char.is_some() {
+        return symbol_bits;
+    }
+
+    let (_, distance_extra_bits, _) = distance_symbol(token);
+    symbol_bits + length_extra_bits as u64 + 5 + distance_extra_bits as u64
+}
+
+fn used_lengths(lengths: &[u8]) -> HashMap<u16, u8> {
+    lengths
+        .iter()
+        .enumerate()
+        .filter(|&(_, &length)| length > 0)
+        .map(|(symbol, &length)| (symbol as u16, length))
+        .collect()
+}
+
+/// A canonical Huffman code over the symbols `0..lengths.len()`, stored as its code lengths.
+struct CodeTable {
+    lengths: Vec<u8>,
+    codes: HashMap<u16, Vec<bool>>,
+    /// Symbols ordered by code, and how many codes every length has, to decode by counting.
+    sorted_symbols: Vec<u16>,
+    length_counts: [u32; MAX_CODE_LENGTH as usize + 1],
+}
+
+impl CodeTable {
+    fn from_frequencies(frequencies: &[u32]) -> Self {
+        let frequencies: Vec<(u16, u32)> = frequencies
+            .iter()
+            .enumerate()
+            .map(|(symbol, &frequency)| (symbol as u16, frequency))
+            .collect();
+
+        let lengths = length_limited_code_lengths(&frequencies, MAX_CODE_LENGTH);
+        let mut table_lengths = vec![0; frequencies.len()];
+        for (symbol, length) in lengths {
+            table_lengths[symbol as usize] = length;
+        }
+
+        Self::from_lengths(table_lengths)
+    }
+
+    fn from_lengths(lengths: Vec<u8>) -> Self {
+        let length_map = used_lengths(&lengths);
+
+        let mut sorted_symbols: Vec<u16> = length_map.keys().copied().collect();
+        sorted_symbols.sort_by_key(|&symbol| (length_map[&symbol], symbol));
+
+        let mut length_counts = [0; MAX_CODE_LENGTH as usize + 1];
+        for &length in length_map.values() {
+            length_counts[length as usize] += 1;
+        }
+
+        Self {
+            codes: assign_canonical_codes(&length_map),
+            lengths,
+            sorted_symbols,
+            length_counts,
+        }
+    }
+
+    /// Lengths after the last used symbol are left out, so only a count comes before them,
+    /// and unused symbols in between take a single zero bit.
+    fn write(&self, writer: &mut BitWriter, count_bits: u32) {
+        let count = self
+            .lengths
+            .iter()
+            .rposition(|&length| length > 0)
+            .map_or(0, |last| last + 1);
+
+        writer.write_n_bits(count_bits, count as u32);
+        for &length in &self.lengths[..count] {
+            writer.write_bit(length > 0);
+            if length > 0 {
+                writer.write_n_bits(CODE_LENGTH_BITS, length as u32);
+            }
+        }
+    }
+
+    fn read(
+        reader: &mut BitReader,
+        count_bits: u32,
+        symbol_count: usize,
+    ) -> Result<Self, LZ77Error> {
+        let mut read_field = |count| reader.read_n_bits(count).ok_or(LZ77Error::PrematureEnd);
+
+        let count = read_field(count_bits)? as usize;
+        if count > symbol_count {
+            return Err(LZ77Error::InvalidCodeTable);
+        }
+
+        let mut lengths = vec![0; symbol_count];
+        for length in &mut lengths[..count] {
+            if read_field(1)? == 1 {
+                *length = read_field(CODE_LENGTH_BITS)? as u8;
+            }
+        }
+
+        // Lengths no prefix code can have would make codes overlap.
+        if !satisfies_kraft(&used_lengths(&lengths)) {
+            return Err(LZ77Error::InvalidCodeTable);
+        }
+
+        Ok(Self::from_lengths(lengths))
+    }
+
+    fn write_symbol(&self, writer: &mut BitWriter, symbol: u16) {
+        for &bit in &self.codes[&symbol] {
+            writer.write_bit(bit);
+        }
+    }
+
+    /// Codes of one length are consecutive numbers starting where the shorter ones left off,
+    /// so a code is found by checking, one bit at a time, whether it falls in that range.
+    fn read_symbol(&self, reader: &mut BitReader) -> Result<u16, LZ77Error> {
+        let mut code = 0;
+        let mut first_code = 0;
+        let mut first_index = 0;
+
+        for &count in &self.length_counts[1..] {
+            code |= reader.read_bit().ok_or(LZ77Error::PrematureEnd)? as u32;
+
+            if code < first_code + count {
+                return Ok(self.sorted_symbols[first_index + (code - first_code) as usize]);
+            }
+
+            first_index += count as usize;
+            first_code = (first_code + count) << 1;
+            code <<= 1;
+        }
+
+        Err(LZ77Error::InvalidCode)
+    }
+}
+
+/// Writes the literal/length and distance code tables built for `tokens`, then every token
+/// as its Huffman code followed by the extra bits of its length and distance buckets.
+pub fn write_tokens(writer: &mut BitWriter, tokens: &[Token]) {
+    let mut literal_length_frequencies =
+        vec![0u32; FIRST_LENGTH_SYMBOL as usize + LENGTH_BASES.len()];
+    let mut distance_frequencies = vec![0u32; DISTANCE_BASES.len()];
+
+    literal_length_frequencies[END_OF_BLOCK as usize] = 1;
+    for token in tokens {
+        literal_length_frequencies[literal_length_symbol(token).0 as usize] += 1;
+        if token.next_char.is_none() {
+            distance_frequencies[distance_symbol(token).0 as usize] += 1;
+        }
+    }
+
+    let literal_lengths = CodeTable::from_frequencies(&literal_length_frequencies);
+    let distances = CodeTable::from_frequencies(&distance_frequencies);
+    literal_lengths.write(writer, LITERAL_LENGTH_COUNT_BITS);
+    distances.write(writer, DISTANCE_COUNT_BITS);
+
+    for token in tokens {
+        let (symbol, extra_bits, extra) = literal_length_symbol(token);
+        literal_lengths.write_symbol(writer, symbol);
+        writer.write_n_bits(extra_bits as u32, extra);
+
+        if token.next_char.is_none() {
+            let (symbol, extra_bits, extra) = distance_symbol(token);
+            distances.write_symbol(writer, symbol);
+            writer.write_n_bits(extra_bits as u32, extra);
+        }
+    }
+
+    literal_lengths.write_symbol(writer, END_OF_BLOCK);
+}
+
+/// Reads the code tables and every token up to the end of the block.
+pub fn read_tokens(reader: &mut BitReader) -> Result<Vec<Token>, LZ77Error> {
+    let literal_symbol_count = FIRST_LENGTH_SYMBOL as usize + LENGTH_BASES.len();
+    let literal_lengths = CodeTable::read(reader, LITERAL_LENGTH_COUNT_BITS, literal_symbol_count)?;
+    let distances = CodeTable::read(reader, DISTANCE_COUNT_BITS, DISTANCE_BASES.len())?;
+
+    let mut tokens = Vec::new();
+    while let Some(token) = read_token(reader, &literal_lengths, &distances)? {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Returns `None` at the end of the block.
+fn read_token(
+    reader: &mut BitReader,
+    literal_lengths: &CodeTable,
+    distances: &CodeTable,
+) -> Result<Option<Token>, LZ77Error> {
+    let symbol = literal_lengths.read_symbol(reader)?;
+    match symbol {
+        0..END_OF_BLOCK => return Ok(Some(Token::literal(symbol as u8))),
+        END_OF_BLOCK => return Ok(None),
+        _ => {}
+    }
+
+    let index = (symbol - FIRST_LENGTH_SYMBOL) as usize;
+    let length = LENGTH_BASES[index] as usize + read_extra_bits(reader, LENGTH_EXTRA_BITS[index])?;
+
+    let index = distances.read_symbol(reader)? as usize;
+    let offset =
+        DISTANCE_BASES[index] as usize + read_extra_bits(reader, DISTANCE_EXTRA_BITS[index])?;
+
+    Ok(Some(Token::reference(offset, length)))
+}
+
+fn read_extra_bits(reader: &mut BitReader, count: u8) -> Result<usize, LZ77Error> {
+    reader
+        .read_n_bits(count as u32)
+        .map(|extra| extra as usize)
+        .ok_or(LZ77Error::PrematureEnd)
+}
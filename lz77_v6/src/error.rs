@@ -0,0 +1,45 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LZ77Error {
+    UnknownFormat,
+    InvalidOffset,
+    PrematureEnd,
+    InvalidCodeTable,
+    InvalidCode,
+    MinMatchLengthOutOfRange,
+}
+
+impl LZ77Error {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LZ77Error::UnknownFormat => "unknown_format",
+            LZ77Error::InvalidOffset => "invalid_offset",
+            LZ77Error::PrematureEnd => "premature_end",
+            LZ77Error::InvalidCodeTable => "invalid_code_table",
+            LZ77Error::InvalidCode => "invalid_code",
+            LZ77Error::MinMatchLengthOutOfRange => "min_match_length_out_of_range",
+        }
+    }
+}
+
+impl fmt::Display for LZ77Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            LZ77Error::UnknownFormat => {
+                "the header names a token format this decoder does not know"
+            }
+            LZ77Error::InvalidOffset => "a match points back further than the data decoded so far",
+            LZ77Error::PrematureEnd => "the data ends before its end marker",
+            LZ77Error::InvalidCodeTable => "a code table holds lengths no prefix code can have",
+            LZ77Error::InvalidCode => "the data holds a code that is not in its code table",
+            LZ77Error::MinMatchLengthOutOfRange => {
+                "the minimum match length is outside what the token format can code"
+            }
+        };
+
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for LZ77Error {}
@@ -1,225 +1,492 @@
 use crate::bit_operations::{BitReader, BitWriter};
-use crate::models::Token;
+use crate::deflate;
+use crate::error::LZ77Error;
+use crate::match_finder::{BinaryTreeMatchFinder, DEFAULT_CHAIN_DEPTH, MatchFinder};
+use crate::models::{Token, TokenFormat};
 use std::cmp::min;
 
+/// LZSS and DEFLATE files start with these bytes and a format byte. Triples never do: read as
+/// a triple header they give 4 offset and 6 length bits followed by a non-zero offset, while
+/// the first triple always has a zero offset.
+const CONTAINER_MAGIC: &[u8; 2] = b"LZ";
+const LZSS_FORMAT: u8 = 1;
+const DEFLATE_FORMAT: u8 = 2;
+const MAX_MIN_MATCH_LENGTH: usize = 15;
+/// Zero bits after the last triple, which with the byte padding are always fewer than a
+/// triple plus this many bits.
+const TRIPLES_END_BITS: u32 = 7;
+
 pub struct LZ77 {
     offset_bits: u8,
     length_bits: u8,
     max_offset: usize,
     max_length: usize,
-    window: Vec<u8>,
-    lab_start_index: usize,
+    chain_depth: usize,
+    lazy_depth: usize,
+    optimal_parsing: bool,
+    token_format: TokenFormat,
+    min_match_length: Option<usize>,
     tokens: Vec<Token>,
 }
 
 impl LZ77 {
+    /// Offsets take up to 15 bits and lengths up to 7, as far as the header fields reach.
     pub fn new(offset_bits: Option<u8>, length_bits: Option<u8>) -> Self {
-        let (offset_bits, length_bits) = (offset_bits.unwrap_or(0), length_bits.unwrap_or(0));
-        let mut max_offset = 0;
-        let mut max_length = 0;
+        let mut lz = Self {
+            offset_bits: 0,
+            length_bits: 0,
+            max_offset: 0,
+            max_length: 0,
+            chain_depth: DEFAULT_CHAIN_DEPTH,
+            lazy_depth: 0,
+            optimal_parsing: false,
+            token_format: TokenFormat::Triples,
+            min_match_length: None,
+            tokens: Vec::new(),
+        };
 
-        if offset_bits > 0 {
-            max_offset = (1 << offset_bits) - 1;
-        }
+        lz.set_field_bits(offset_bits.unwrap_or(0), length_bits.unwrap_or(0));
+        lz
+    }
 
-        if length_bits > 0 {
-            max_length = (1 << length_bits) - 1
-        }
+    /// Flag-bit formats end on a zero offset, so they need at least one offset bit. DEFLATE
+    /// codes lengths through its own buckets up to 258 bytes, so it keeps no length bits.
+    fn set_field_bits(&mut self, offset_bits: u8, length_bits: u8) {
+        let min_offset_bits = match self.token_format {
+            TokenFormat::Triples => 0,
+            TokenFormat::Lzss | TokenFormat::Deflate => 1,
+        };
 
-        Self {
-            offset_bits,
-            length_bits,
-            max_offset,
-            max_length,
-            window: Vec::new(),
-            lab_start_index: 0,
-            tokens: Vec::new(),
+        self.offset_bits = offset_bits.clamp(min_offset_bits, 15);
+        self.length_bits = match self.token_format {
+            TokenFormat::Deflate => 0,
+            TokenFormat::Triples | TokenFormat::Lzss => length_bits.min(7),
+        };
+        self.max_offset = (1 << self.offset_bits) - 1;
+        self.max_length = (1 << self.length_bits) - 1;
+    }
+
+    /// How many earlier positions with the same 3-byte prefix are tried per match; higher
+    /// values find longer matches more slowly.
+    pub fn with_chain_depth(mut self, chain_depth: Option<usize>) -> Self {
+        self.chain_depth = chain_depth.unwrap_or(DEFAULT_CHAIN_DEPTH).max(1);
+        self
+    }
+
+    /// How many literals in a row greedy parsing may emit while a literal and the match at
+    /// the next byte keep costing fewer bits. Zero takes the longest match at every position.
+    pub fn with_lazy_depth(mut self, lazy_depth: Option<usize>) -> Self {
+        self.lazy_depth = lazy_depth.unwrap_or(0);
+        self
+    }
+
+    /// Picks tokens by optimal parsing over every match a binary tree finds, instead of
+    /// taking the longest match at each position. Overrides the chain and lazy depths.
+    pub fn with_optimal_parsing(mut self, optimal_parsing: bool) -> Self {
+        self.optimal_parsing = optimal_parsing;
+        self
+    }
+
+    pub fn with_token_format(mut self, token_format: TokenFormat) -> Self {
+        self.token_format = token_format;
+        self.set_field_bits(self.offset_bits, self.length_bits);
+        self
+    }
+
+    /// Shorter LZSS and DEFLATE matches are sent as literals. For LZSS it defaults to the
+    /// shortest match whose token takes fewer bits than its bytes would as literals. LZSS takes
+    /// 1 to 15 and DEFLATE, whose length codes start at 3, 3 to 15; `encode` rejects anything
+    /// else. Triples ignore it.
+    pub fn with_min_match_length(mut self, min_match_length: Option<usize>) -> Self {
+        self.min_match_length = min_match_length;
+        self
+    }
+
+    fn min_match_length(&self) -> usize {
+        match self.token_format {
+            TokenFormat::Triples => 1,
+            TokenFormat::Lzss => {
+                let break_even = (1 + self.offset_bits as usize + self.length_bits as usize) / 9;
+                self.min_match_length
+                    .unwrap_or(break_even + 1)
+                    .clamp(1, MAX_MIN_MATCH_LENGTH)
+            }
+            TokenFormat::Deflate => self
+                .min_match_length
+                .unwrap_or(deflate::MIN_MATCH_LENGTH)
+                .clamp(deflate::MIN_MATCH_LENGTH, MAX_MIN_MATCH_LENGTH),
         }
     }
 
-    pub fn encode(&mut self, input_data: &[u8]) -> Vec<u8> {
-        self.tokens.clear();
-        let mut writer = BitWriter::new();
+    pub fn encode(&mut self, input_data: &[u8]) -> Result<Vec<u8>, LZ77Error> {
+        let min_match_length_fits = match (self.token_format, self.min_match_length) {
+            (TokenFormat::Triples, _) | (_, None) => true,
+            (TokenFormat::Lzss, Some(length)) => (1..=MAX_MIN_MATCH_LENGTH).contains(&length),
+            (TokenFormat::Deflate, Some(length)) => {
+                (deflate::MIN_MATCH_LENGTH..=MAX_MIN_MATCH_LENGTH).contains(&length)
+            }
+        };
+        if !min_match_length_fits {
+            return Err(LZ77Error::MinMatchLengthOutOfRange);
+        }
 
-        self.write_header(&mut writer);
+        self.tokens = if self.optimal_parsing {
+            self.parse_optimal(input_data)
+        } else {
+            self.parse_greedy(input_data)
+        };
 
-        let bytes_read = self.initialize_window(input_data);
-        let mut input_position = bytes_read;
+        let mut writer = BitWriter::new();
 
-        while !self.get_lookahead_buffer().is_empty() {
-            let (best_match_offset, best_match_length) = self.find_longest_match();
-            let next_char = self.get_lookahead_buffer()[best_match_length];
+        self.write_header(&mut writer);
 
-            self.tokens
-                .push(Token::new(best_match_offset, best_match_length, next_char));
+        if self.token_format == TokenFormat::Deflate {
+            deflate::write_tokens(&mut writer, &self.tokens);
+            return Ok(writer.finish());
+        }
 
-            self.emit_token(&mut writer, best_match_offset, best_match_length, next_char);
-            self.slide_window(best_match_length + 1, input_data, &mut input_position);
+        for token in &self.tokens {
+            self.emit_token(&mut writer, token);
         }
 
-        writer.write_n_bits(7, 0);
-        writer.finish()
+        if self.token_format == TokenFormat::Triples {
+            writer.write_n_bits(TRIPLES_END_BITS, 0);
+        } else {
+            // A match flag with offset 0 marks the end, so padding never reads as a token.
+            writer.write_bit(true);
+            writer.write_n_bits(self.offset_bits as u32, 0);
+        }
+        Ok(writer.finish())
     }
 
-    fn write_header(&self, writer: &mut BitWriter) {
-        writer.write_n_bits(4, self.offset_bits as u32);
-        writer.write_n_bits(3, self.length_bits as u32);
+    /// Triples keep the next char in the `max_length`-byte look-ahead buffer, while LZSS
+    /// lengths are stored above the minimum match length.
+    fn max_match_length(&self, remaining: usize) -> usize {
+        match self.token_format {
+            TokenFormat::Triples => min(self.max_length, remaining).saturating_sub(1),
+            TokenFormat::Lzss => min(self.min_match_length() + self.max_length, remaining),
+            TokenFormat::Deflate => min(deflate::MAX_MATCH_LENGTH, remaining),
+        }
     }
 
-    fn initialize_window(&mut self, data: &[u8]) -> usize {
-        let bytes_to_read = min(self.max_length, data.len());
-        self.lab_start_index = 0;
-
-        self.window.clear();
-        if bytes_to_read > 0 {
-            self.window.extend_from_slice(&data[..bytes_to_read]);
+    /// The token for a match of `length` bytes at `position`, or for a literal when
+    /// `length` is 0.
+    fn token_at(&self, input_data: &[u8], position: usize, offset: usize, length: usize) -> Token {
+        match self.token_format {
+            TokenFormat::Triples => Token::new(offset, length, input_data[position + length]),
+            TokenFormat::Lzss | TokenFormat::Deflate if length == 0 => {
+                Token::literal(input_data[position])
+            }
+            TokenFormat::Lzss | TokenFormat::Deflate => Token::reference(offset, length),
         }
-
-        bytes_to_read
     }
 
-    fn find_longest_match(&self) -> (usize, usize) {
-        let search_buffer = self.get_search_buffer();
-        let look_ahead_buffer = self.get_lookahead_buffer();
+    fn parse_greedy(&self, input_data: &[u8]) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut match_finder = MatchFinder::new(input_data, self.chain_depth);
+        let min_match_length = self.min_match_length();
+        let mut find_match = |position: usize| {
+            let (offset, length) = match_finder.find_longest_match(
+                position,
+                self.max_offset,
+                self.max_match_length(input_data.len() - position),
+            );
+
+            if length < min_match_length {
+                (0, 0)
+            } else {
+                (offset, length)
+            }
+        };
 
-        if search_buffer.is_empty() || look_ahead_buffer.is_empty() {
-            return (0, 0);
+        let mut position = 0;
+        let mut deferred_match = None;
+        let mut literals_in_a_row = 0;
+
+        while position < input_data.len() {
+            let (best_match_offset, best_match_length) = deferred_match
+                .take()
+                .unwrap_or_else(|| find_match(position));
+
+            let token = self.token_at(input_data, position, best_match_offset, best_match_length);
+
+            // Lazy evaluation: a literal and then the match one byte further on is taken when
+            // it costs fewer bits than this match followed by the rest of that next match, which
+            // ends at the same position. Under triples a literal costs a whole token, so a
+            // literal first never wins there.
+            let can_defer = best_match_length > 0
+                && literals_in_a_row < self.lazy_depth
+                && position + 1 < input_data.len();
+            if can_defer {
+                let (next_offset, next_length) = find_match(position + 1);
+                let literal = self.token_at(input_data, position, 0, 0);
+                let next = self.token_at(input_data, position + 1, next_offset, next_length);
+
+                let match_end = position + token.span();
+                let deferred_end = position + literal.span() + next.span();
+                let cheaper = next_length > 0 && deferred_end > match_end && {
+                    let deferred_bits = self.token_bits(&literal) + self.token_bits(&next);
+                    let tail_length = deferred_end - match_end;
+                    let match_bits = self.token_bits(&token)
+                        + self.match_tail_bits(input_data, match_end, next_offset, tail_length);
+                    deferred_bits < match_bits
+                };
+                if cheaper {
+                    tokens.push(literal);
+                    position += 1;
+                    literals_in_a_row += 1;
+                    deferred_match = Some((next_offset, next_length));
+                    continue;
+                }
+            }
+            literals_in_a_row = 0;
+
+            position += token.span();
+            tokens.push(token);
         }
 
-        let mut best_match_offset = 0;
-        let mut best_match_length = 0;
+        tokens
+    }
 
-        let max_match_length = min(self.max_length, look_ahead_buffer.len().saturating_sub(1));
+    /// Bits to code the `length` bytes at `position` as the tail of a match at `offset`, or as
+    /// literals when the tail is too short to be a match of its own.
+    fn match_tail_bits(
+        &self,
+        input_data: &[u8],
+        position: usize,
+        offset: usize,
+        length: usize,
+    ) -> u64 {
+        if self.token_format == TokenFormat::Triples {
+            return self.token_bits(&self.token_at(input_data, position, offset, length - 1));
+        }
 
-        for search_buffer_pos in 0..search_buffer.len() {
-            let mut match_length = 0;
+        if length >= self.min_match_length() {
+            return self.token_bits(&Token::reference(offset, length));
+        }
 
-            for i in 0..max_match_length {
-                let window_pos = search_buffer_pos + i;
+        (position..position + length)
+            .map(|literal| self.token_bits(&self.token_at(input_data, literal, 0, 0)))
+            .sum()
+    }
 
-                if self.window.len() <= window_pos {
-                    break;
+    /// Shortest path from the start of the input to its end, where every token that could be
+    /// emitted at a position is an edge weighted by its size in bits.
+    fn parse_optimal(&self, input_data: &[u8]) -> Vec<Token> {
+        let mut match_finder = BinaryTreeMatchFinder::new(input_data);
+        let min_match_length = self.min_match_length();
+
+        // Cheapest size in bits of the output up to every position, and the token that ends
+        // there on that path.
+        let mut cost = vec![u64::MAX; input_data.len() + 1];
+        let mut last_token = vec![Token::literal(0); input_data.len() + 1];
+        cost[0] = 0;
+
+        for position in 0..input_data.len() {
+            let matches = match_finder.find_matches(
+                position,
+                self.max_offset,
+                self.max_match_length(input_data.len() - position),
+            );
+
+            let mut relax = |token: Token| {
+                let end = position + token.span();
+                let end_cost = cost[position] + self.token_bits(&token);
+                if end_cost < cost[end] {
+                    cost[end] = end_cost;
+                    last_token[end] = token;
                 }
+            };
 
-                if self.window[window_pos] != look_ahead_buffer[i] {
-                    break;
+            relax(self.token_at(input_data, position, 0, 0));
+            let mut shorter_length = 0;
+            for (offset, length) in matches {
+                for length in (shorter_length + 1).max(min_match_length)..=length {
+                    relax(self.token_at(input_data, position, offset, length));
                 }
-
-                match_length += 1;
+                shorter_length = length;
             }
+        }
 
-            if best_match_length < match_length {
-                best_match_length = match_length;
-                best_match_offset = self.lab_start_index - search_buffer_pos;
-            }
+        let mut tokens = Vec::new();
+        let mut end = input_data.len();
+        while end > 0 {
+            let token = last_token[end].clone();
+            end -= token.span();
+            tokens.push(token);
         }
 
-        (best_match_offset, best_match_length)
+        tokens.reverse();
+        tokens
     }
 
-    fn emit_token(&self, writer: &mut BitWriter, offset: usize, length: usize, next_char: u8) {
-        writer.write_n_bits(self.offset_bits as u32, offset as u32);
-        writer.write_n_bits(self.length_bits as u32, length as u32);
-        writer.write_n_bits(8, next_char as u32);
-    }
+    fn token_bits(&self, token: &Token) -> u64 {
+        let (offset_bits, length_bits) = (self.offset_bits as u64, self.length_bits as u64);
 
-    fn slide_window(&mut self, n: usize, input: &[u8], input_position: &mut usize) {
-        self.lab_start_index += n;
+        match self.token_format {
+            TokenFormat::Triples => offset_bits + length_bits + 8,
+            TokenFormat::Deflate => deflate::estimated_token_bits(token),
+            TokenFormat::Lzss if token.next_char.is_some() => 1 + 8,
+            TokenFormat::Lzss => 1 + offset_bits + length_bits,
+        }
+    }
 
-        let bytes_to_read = min(n, input.len() - *input_position);
+    /// LZSS and DEFLATE files put the container magic and their format byte in front of the
+    /// usual header. LZSS adds the minimum match length, while DEFLATE drops the length bits
+    /// and its tokens follow their own code tables.
+    fn write_header(&self, writer: &mut BitWriter) {
+        let format = match self.token_format {
+            TokenFormat::Triples => None,
+            TokenFormat::Lzss => Some(LZSS_FORMAT),
+            TokenFormat::Deflate => Some(DEFLATE_FORMAT),
+        };
+        if let Some(format) = format {
+            for &byte in CONTAINER_MAGIC {
+                writer.write_n_bits(8, byte as u32);
+            }
+            writer.write_n_bits(8, format as u32);
+        }
 
-        if bytes_to_read > 0 {
-            self.window
-                .extend_from_slice(&input[*input_position..*input_position + bytes_to_read]);
-            *input_position += bytes_to_read;
+        writer.write_n_bits(4, self.offset_bits as u32);
+        if self.token_format != TokenFormat::Deflate {
+            writer.write_n_bits(3, self.length_bits as u32);
         }
 
-        if self.lab_start_index > self.max_offset {
-            let excess = self.lab_start_index - self.max_offset;
+        if self.token_format == TokenFormat::Lzss {
+            writer.write_n_bits(4, self.min_match_length() as u32);
+        }
+    }
 
-            self.window.drain(0..excess);
-            self.lab_start_index -= excess;
+    fn emit_token(&self, writer: &mut BitWriter, token: &Token) {
+        match (self.token_format, token.next_char) {
+            (TokenFormat::Triples, next_char) => {
+                writer.write_n_bits(self.offset_bits as u32, token.offset as u32);
+                writer.write_n_bits(self.length_bits as u32, token.match_length as u32);
+                writer.write_n_bits(8, next_char.unwrap_or_default() as u32);
+            }
+            (_, Some(byte)) => {
+                writer.write_bit(false);
+                writer.write_n_bits(8, byte as u32);
+            }
+            (_, None) => {
+                writer.write_bit(true);
+                writer.write_n_bits(self.offset_bits as u32, token.offset as u32);
+                writer.write_n_bits(
+                    self.length_bits as u32,
+                    (token.match_length - self.min_match_length()) as u32,
+                );
+            }
         }
     }
 
-    pub fn decode(&mut self, encoded_data: &[u8]) -> Vec<u8> {
-        let mut reader = BitReader::new(encoded_data);
+    pub fn decode(&mut self, encoded_data: &[u8]) -> Result<Vec<u8>, LZ77Error> {
         let mut output: Vec<u8> = Vec::new();
 
-        let (offset_bits, length_bits) = match Self::read_header(&mut reader) {
-            (offset, length) => (offset, length),
-        };
+        self.token_format = TokenFormat::Triples;
+        let mut header_bytes = 0;
+        if let Some(rest) = encoded_data.strip_prefix(CONTAINER_MAGIC) {
+            self.token_format = match rest.first() {
+                Some(&LZSS_FORMAT) => TokenFormat::Lzss,
+                Some(&DEFLATE_FORMAT) => TokenFormat::Deflate,
+                _ => return Err(LZ77Error::UnknownFormat),
+            };
+            header_bytes = CONTAINER_MAGIC.len() + 1;
+        }
 
-        self.offset_bits = offset_bits;
-        self.length_bits = length_bits;
-        self.max_offset = (1 << self.offset_bits) - 1;
-        self.max_length = (1 << self.length_bits) - 1;
+        let mut reader = BitReader::new(&encoded_data[header_bytes..]);
+        self.read_header(&mut reader);
 
-        while let Some(token) = self.read_token(&mut reader) {
-            self.decode_token(&token, &mut output);
+        if self.token_format == TokenFormat::Deflate {
+            for token in deflate::read_tokens(&mut reader)? {
+                self.decode_token(&token, &mut output)?;
+            }
+            return Ok(output);
         }
 
-        output
+        while let Some(token) = self.read_token(&mut reader)? {
+            self.decode_token(&token, &mut output)?;
+        }
+
+        Ok(output)
     }
 
-    fn read_header(reader: &mut BitReader) -> (u8, u8) {
-        let offset_bits = match reader.read_n_bits(4) {
-            Some(value) => value as u8,
-            None => 0,
-        };
-        let length_bits = match reader.read_n_bits(3) {
-            Some(value) => value as u8,
-            None => 0,
+    /// Reads the fields after the container bytes, which `decode` has already matched.
+    fn read_header(&mut self, reader: &mut BitReader) {
+        let mut read_field = |count| reader.read_n_bits(count).unwrap_or(0) as u8;
+
+        let offset_bits = read_field(4);
+        let length_bits = match self.token_format {
+            TokenFormat::Deflate => 0,
+            TokenFormat::Triples | TokenFormat::Lzss => read_field(3),
         };
-        (offset_bits, length_bits)
+        if self.token_format == TokenFormat::Lzss {
+            self.min_match_length = Some(read_field(4) as usize);
+        }
+
+        self.set_field_bits(offset_bits, length_bits);
     }
 
-    fn read_token(&self, reader: &mut BitReader) -> Option<Token> {
-        let offset_raw = match reader.read_n_bits(self.offset_bits as u32) {
-            Some(v) => v,
-            None => return None,
-        };
+    /// Returns `None` at the end of the tokens. LZSS streams name their end with a marker, so
+    /// running out of bits before it means the data was cut short.
+    fn read_token(&self, reader: &mut BitReader) -> Result<Option<Token>, LZ77Error> {
+        if self.token_format == TokenFormat::Lzss {
+            let mut read_field = |count| reader.read_n_bits(count).ok_or(LZ77Error::PrematureEnd);
 
-        let length_raw = match reader.read_n_bits(self.length_bits as u32) {
-            Some(v) => v,
-            None => return None,
-        };
+            if read_field(1)? == 0 {
+                return Ok(Some(Token::literal(read_field(8)? as u8)));
+            }
 
-        let ch_raw = match reader.read_n_bits(8) {
-            Some(v) => v,
-            None => return None,
-        };
+            let offset = read_field(self.offset_bits as u32)? as usize;
+            if offset == 0 {
+                return Ok(None);
+            }
+            let length = read_field(self.length_bits as u32)? as usize;
+
+            return Ok(Some(Token::reference(
+                offset,
+                length + self.min_match_length(),
+            )));
+        }
+
+        // Only the end bits and padding follow the last triple, so a triple that would leave
+        // fewer bits than those behind it is padding rather than a token.
+        let triple_bits = self.offset_bits as usize + self.length_bits as usize + 8;
+        if reader.bits_remaining() < triple_bits + TRIPLES_END_BITS as usize {
+            return Ok(None);
+        }
 
-        Some(Token::new(
+        let mut read_field = |count| reader.read_n_bits(count).unwrap_or(0);
+        let offset_raw = read_field(self.offset_bits as u32);
+        let length_raw = read_field(self.length_bits as u32);
+        let ch_raw = read_field(8);
+
+        Ok(Some(Token::new(
             offset_raw as usize,
             length_raw as usize,
             ch_raw as u8,
-        ))
+        )))
     }
 
-    fn decode_token(&self, token: &Token, output: &mut Vec<u8>) {
-        let match_start_position = output.len() - token.offset;
-
-        if token.offset == 0 || token.match_length == 0 {
-            output.push(token.next_char);
-            return;
+    fn decode_token(&self, token: &Token, output: &mut Vec<u8>) -> Result<(), LZ77Error> {
+        if token.offset > output.len() {
+            return Err(LZ77Error::InvalidOffset);
         }
 
-        for i in 0..token.match_length {
-            let index = match_start_position + i;
-            let symbol = output[index];
-            output.push(symbol);
-        }
+        if token.offset != 0 && token.match_length != 0 {
+            let match_start_position = output.len() - token.offset;
 
-        output.push(token.next_char);
-    }
+            for i in 0..token.match_length {
+                let index = match_start_position + i;
+                let symbol = output[index];
+                output.push(symbol);
+            }
+        }
 
-    fn get_search_buffer(&self) -> &[u8] {
-        &self.window[0..self.lab_start_index]
-    }
+        if let Some(next_char) = token.next_char {
+            output.push(next_char);
+        }
 
-    fn get_lookahead_buffer(&self) -> &[u8] {
-        &self.window[self.lab_start_index..]
+        Ok(())
     }
 
     pub fn calculate_compression_ratio(original_size: usize, compressed_size: usize) -> f64 {
@@ -233,3 +500,182 @@ impl LZ77 {
         self.tokens.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_input(length: usize) -> Vec<u8> {
+        b"abracadabra, \0alakazam\0\0 "
+            .iter()
+            .cycle()
+            .take(length)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn triples_decode_without_padding_tokens() {
+        for length in [0, 1, 2, 7, 5000] {
+            let data = sample_input(length);
+
+            for offset_bits in 0..=15 {
+                for length_bits in 0..=7 {
+                    let encoded = LZ77::new(Some(offset_bits), Some(length_bits))
+                        .encode(&data)
+                        .unwrap();
+                    let decoded = LZ77::new(None, None).decode(&encoded).unwrap();
+
+                    assert_eq!(decoded, data, "o{offset_bits} l{length_bits}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flag_bit_formats_round_trip() {
+        let data = sample_input(5000);
+
+        for token_format in [TokenFormat::Lzss, TokenFormat::Deflate] {
+            for optimal_parsing in [false, true] {
+                let encoded = LZ77::new(Some(12), Some(4))
+                    .with_token_format(token_format)
+                    .with_optimal_parsing(optimal_parsing)
+                    .encode(&data)
+                    .unwrap();
+                let decoded = LZ77::new(None, None).decode(&encoded).unwrap();
+
+                assert_eq!(decoded, data, "{token_format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn lazy_parsing_never_grows_the_output() {
+        let inputs: [&[u8]; 2] = [
+            include_bytes!("../test_input.txt"),
+            include_bytes!("../input_chinese.txt"),
+        ];
+        let formats = [
+            TokenFormat::Triples,
+            TokenFormat::Lzss,
+            TokenFormat::Deflate,
+        ];
+
+        for (data, token_format) in inputs.iter().flat_map(|&data| formats.map(|f| (data, f))) {
+            for (offset_bits, length_bits) in [(4, 2), (8, 3), (12, 4), (15, 7)] {
+                let encoder = || {
+                    LZ77::new(Some(offset_bits), Some(length_bits))
+                        .with_token_format(token_format)
+                        .with_chain_depth(Some(1))
+                };
+                let greedy = encoder().encode(data).unwrap();
+
+                for lazy_depth in 1..=4 {
+                    let lazy = encoder()
+                        .with_lazy_depth(Some(lazy_depth))
+                        .encode(data)
+                        .unwrap();
+
+                    assert!(
+                        lazy.len() <= greedy.len(),
+                        "{token_format:?} o{offset_bits} l{length_bits} lazy {lazy_depth}"
+                    );
+                    assert_eq!(LZ77::new(None, None).decode(&lazy).unwrap(), data);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_lzss_streams_are_rejected() {
+        let data = sample_input(500);
+        let encoded = LZ77::new(Some(8), Some(3))
+            .with_token_format(TokenFormat::Lzss)
+            .encode(&data)
+            .unwrap();
+
+        for end in CONTAINER_MAGIC.len() + 1..encoded.len() {
+            let decoded = LZ77::new(None, None).decode(&encoded[..end]);
+            assert_eq!(decoded, Err(LZ77Error::PrematureEnd), "cut at {end}");
+        }
+    }
+
+    #[test]
+    fn truncated_deflate_streams_are_rejected() {
+        let data = sample_input(500);
+        let encoded = LZ77::new(Some(8), Some(3))
+            .with_token_format(TokenFormat::Deflate)
+            .encode(&data)
+            .unwrap();
+
+        for end in CONTAINER_MAGIC.len() + 1..encoded.len() {
+            let decoded = LZ77::new(None, None).decode(&encoded[..end]);
+            assert_eq!(decoded, Err(LZ77Error::PrematureEnd), "cut at {end}");
+        }
+    }
+
+    #[test]
+    fn deflate_tables_without_a_prefix_code_are_rejected() {
+        // Three literal/length codes of one bit each, which no prefix code can hold.
+        let mut writer = BitWriter::new();
+        for &byte in CONTAINER_MAGIC.iter().chain(&[DEFLATE_FORMAT]) {
+            writer.write_n_bits(8, byte as u32);
+        }
+        writer.write_n_bits(4, 8);
+        writer.write_n_bits(9, 3);
+        for _ in 0..3 {
+            writer.write_bit(true);
+            writer.write_n_bits(4, 1);
+        }
+
+        let decoded = LZ77::new(None, None).decode(&writer.finish());
+        assert_eq!(decoded, Err(LZ77Error::InvalidCodeTable));
+    }
+
+    #[test]
+    fn min_match_lengths_outside_the_format_are_rejected() {
+        let data = sample_input(500);
+
+        for (token_format, min_match_length, accepted) in [
+            (TokenFormat::Lzss, 0, false),
+            (TokenFormat::Lzss, 1, true),
+            (TokenFormat::Lzss, 15, true),
+            (TokenFormat::Lzss, 16, false),
+            (TokenFormat::Deflate, 2, false),
+            (TokenFormat::Deflate, 3, true),
+            (TokenFormat::Deflate, 16, false),
+        ] {
+            let encoded = LZ77::new(Some(8), Some(3))
+                .with_token_format(token_format)
+                .with_min_match_length(Some(min_match_length))
+                .encode(&data);
+
+            match encoded {
+                Ok(encoded) => {
+                    assert!(accepted, "{token_format:?} {min_match_length}");
+                    assert_eq!(LZ77::new(None, None).decode(&encoded).unwrap(), data);
+                }
+                Err(error) => {
+                    assert!(!accepted, "{token_format:?} {min_match_length}");
+                    assert_eq!(error, LZ77Error::MinMatchLengthOutOfRange);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_past_the_output_are_rejected() {
+        // offset_bits 4, length_bits 2, then the triple (3, 1, 'a') and the end bits.
+        let mut writer = BitWriter::new();
+        writer.write_n_bits(4, 4);
+        writer.write_n_bits(3, 2);
+        writer.write_n_bits(4, 3);
+        writer.write_n_bits(2, 1);
+        writer.write_n_bits(8, b'a' as u32);
+        writer.write_n_bits(TRIPLES_END_BITS, 0);
+
+        let decoded = LZ77::new(None, None).decode(&writer.finish());
+        assert_eq!(decoded, Err(LZ77Error::InvalidOffset));
+    }
+}
@@ -5,7 +5,10 @@ use crate::api::{decode_file, encode_file, index};
 
 mod api;
 mod bit_operations;
+mod deflate;
+mod error;
 mod lz77;
+mod match_finder;
 mod models;
 
 #[actix_web::main]
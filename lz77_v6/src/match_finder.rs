@@ -0,0 +1,219 @@
+pub const DEFAULT_CHAIN_DEPTH: usize = 64;
+
+const HASH_BITS: u32 = 15;
+const MAX_TREE_DEPTH: usize = 4096;
+const HASHED_LENGTH: usize = 3;
+const NO_POSITION: u32 = u32::MAX;
+
+/// Hash chains over the 3-byte prefixes of the input: `head` holds the latest position of
+/// every hash and `previous` links each position to the one before it with the same hash.
+/// Matches shorter than the prefix are looked up through the latest occurrence of every byte
+/// and byte pair, since for those the nearest occurrence is as good as any other.
+pub struct MatchFinder<'a> {
+    data: &'a [u8],
+    head: Vec<u32>,
+    previous: Vec<u32>,
+    last_pair: Vec<u32>,
+    last_byte: Vec<u32>,
+    chain_depth: usize,
+    inserted: usize,
+}
+
+impl<'a> MatchFinder<'a> {
+    pub fn new(data: &'a [u8], chain_depth: usize) -> Self {
+        Self {
+            data,
+            head: vec![NO_POSITION; 1 << HASH_BITS],
+            previous: vec![NO_POSITION; data.len()],
+            last_pair: vec![NO_POSITION; 1 << 16],
+            last_byte: vec![NO_POSITION; 1 << 8],
+            chain_depth: chain_depth.max(1),
+            inserted: 0,
+        }
+    }
+
+    /// Returns `(offset, length)` of the longest match for `position` that starts at most
+    /// `max_offset` bytes back and is no longer than `max_length`, or `(0, 0)` if none does.
+    /// Positions have to be queried in increasing order.
+    pub fn find_longest_match(
+        &mut self,
+        position: usize,
+        max_offset: usize,
+        max_length: usize,
+    ) -> (usize, usize) {
+        self.insert_up_to(position);
+
+        let max_length = max_length.min(self.data.len() - position);
+        let window_start = position.saturating_sub(max_offset);
+        let mut best_match = (0, 0);
+
+        if max_length >= HASHED_LENGTH {
+            let mut candidate = self.head[self.hash(position)];
+
+            for _ in 0..self.chain_depth {
+                if candidate == NO_POSITION || (candidate as usize) < window_start {
+                    break;
+                }
+
+                let length = self.match_length(candidate as usize, position, max_length);
+                if best_match.1 < length {
+                    best_match = (position - candidate as usize, length);
+                    if length == max_length {
+                        break;
+                    }
+                }
+
+                candidate = self.previous[candidate as usize];
+            }
+        }
+
+        if best_match.1 < HASHED_LENGTH {
+            let short_candidates = [
+                (max_length >= 2).then(|| self.last_pair[self.pair(position)]),
+                (max_length >= 1).then(|| self.last_byte[self.data[position] as usize]),
+            ];
+
+            for candidate in short_candidates.into_iter().flatten() {
+                if candidate == NO_POSITION || (candidate as usize) < window_start {
+                    continue;
+                }
+
+                let length = self.match_length(candidate as usize, position, max_length);
+                if best_match.1 < length {
+                    best_match = (position - candidate as usize, length);
+                }
+            }
+        }
+
+        best_match
+    }
+
+    fn insert_up_to(&mut self, position: usize) {
+        while self.inserted < position {
+            let inserted = self.inserted;
+
+            if inserted + HASHED_LENGTH <= self.data.len() {
+                let hash = self.hash(inserted);
+                self.previous[inserted] = self.head[hash];
+                self.head[hash] = inserted as u32;
+            }
+            if inserted + 2 <= self.data.len() {
+                let pair = self.pair(inserted);
+                self.last_pair[pair] = inserted as u32;
+            }
+            self.last_byte[self.data[inserted] as usize] = inserted as u32;
+
+            self.inserted += 1;
+        }
+    }
+
+    /// The match may run on past `position`, which the decoder handles by copying byte by byte.
+    fn match_length(&self, candidate: usize, position: usize, max_length: usize) -> usize {
+        self.data[candidate..]
+            .iter()
+            .zip(&self.data[position..position + max_length])
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let prefix = (self.data[position] as u32) << 16
+            | (self.data[position + 1] as u32) << 8
+            | self.data[position + 2] as u32;
+
+        (prefix.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn pair(&self, position: usize) -> usize {
+        (self.data[position] as usize) << 8 | self.data[position + 1] as usize
+    }
+}
+
+/// Binary search trees of the suffixes seen so far, one per first byte. Every position is
+/// inserted as the new root of its tree, so nodes only get older further down and the walk
+/// can stop at the first one that has left the window. Walking down the tree meets every
+/// match length the window holds, not just the longest one.
+pub struct BinaryTreeMatchFinder<'a> {
+    data: &'a [u8],
+    roots: Vec<u32>,
+    children: Vec<[u32; 2]>,
+}
+
+impl<'a> BinaryTreeMatchFinder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            roots: vec![NO_POSITION; 1 << 8],
+            children: vec![[NO_POSITION; 2]; data.len()],
+        }
+    }
+
+    /// Inserts `position` and returns `(offset, length)` for every match length it can
+    /// reach, shortest first; each length also covers every shorter one at the same offset.
+    /// Every position has to be passed in increasing order.
+    pub fn find_matches(
+        &mut self,
+        position: usize,
+        max_offset: usize,
+        max_length: usize,
+    ) -> Vec<(usize, usize)> {
+        let max_length = max_length.min(self.data.len() - position);
+        let window_start = position.saturating_sub(max_offset);
+
+        let root = &mut self.roots[self.data[position] as usize];
+        let mut candidate = *root;
+        *root = position as u32;
+
+        // Where the next node smaller or greater than `position` gets linked in, and how
+        // many bytes every node on that side is already known to share with it.
+        let mut smaller_slot = (position, 0);
+        let mut greater_slot = (position, 1);
+        let mut smaller_length = 0;
+        let mut greater_length = 0;
+
+        let mut matches = Vec::new();
+        for _ in 0..MAX_TREE_DEPTH {
+            if candidate == NO_POSITION || (candidate as usize) < window_start {
+                break;
+            }
+            let node = candidate as usize;
+
+            let mut length = smaller_length.min(greater_length);
+            while length < max_length && self.data[node + length] == self.data[position + length] {
+                length += 1;
+            }
+
+            if matches.last().map_or(0, |&(_, best_length)| best_length) < length {
+                matches.push((position - node, length));
+            }
+
+            // Equal as far as matches can reach, so the newer position takes the node's place.
+            if length == max_length {
+                let [smaller, greater] = self.children[node];
+                self.link(smaller_slot, smaller);
+                self.link(greater_slot, greater);
+                return matches;
+            }
+
+            candidate = if self.data[node + length] < self.data[position + length] {
+                self.link(smaller_slot, candidate);
+                smaller_slot = (node, 1);
+                smaller_length = length;
+                self.children[node][1]
+            } else {
+                self.link(greater_slot, candidate);
+                greater_slot = (node, 0);
+                greater_length = length;
+                self.children[node][0]
+            };
+        }
+
+        self.link(smaller_slot, NO_POSITION);
+        self.link(greater_slot, NO_POSITION);
+        matches
+    }
+
+    fn link(&mut self, (node, side): (usize, usize), child: u32) {
+        self.children[node][side] = child;
+    }
+}
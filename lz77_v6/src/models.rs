@@ -4,7 +4,8 @@ use serde::{Deserialize, Serialize};
 pub struct Token {
     pub offset: usize,
     pub match_length: usize,
-    pub next_char: u8,
+    /// Always set in triples; LZSS tokens carry either a char or a match.
+    pub next_char: Option<u8>,
 }
 
 impl Token {
@@ -12,9 +13,38 @@ impl Token {
         Self {
             offset,
             match_length: length,
-            next_char,
+            next_char: Some(next_char),
         }
     }
+
+    pub fn literal(byte: u8) -> Self {
+        Self::new(0, 0, byte)
+    }
+
+    pub fn reference(offset: usize, length: usize) -> Self {
+        Self {
+            offset,
+            match_length: length,
+            next_char: None,
+        }
+    }
+
+    /// How many bytes of input the token stands for.
+    pub fn span(&self) -> usize {
+        self.match_length + self.next_char.is_some() as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenFormat {
+    /// `(offset, length, next char)` triples with fixed-width fields.
+    #[default]
+    Triples,
+    /// A flag bit in front of every token tells a literal byte from an `(offset, length)` pair.
+    Lzss,
+    /// Literals, match lengths and offsets coded with Huffman tables as DEFLATE does.
+    Deflate,
 }
 
 #[derive(Debug, Deserialize)]
@@ -22,7 +52,16 @@ pub struct EncodeRequest {
     pub filename: String,
     pub file_data: Vec<u8>,
     pub offset_bits: u8,
+    /// Ignored by DEFLATE, whose matches run up to 258 bytes.
     pub length_bits: u8,
+    pub chain_depth: Option<usize>,
+    pub lazy_depth: Option<usize>,
+    #[serde(default)]
+    pub optimal_parsing: bool,
+    #[serde(default)]
+    pub token_format: TokenFormat,
+    /// 1 to 15 for LZSS and 3 to 15 for DEFLATE; anything else is rejected. Unused by triples.
+    pub min_match_length: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -32,6 +71,8 @@ pub struct EncodeResponse {
     pub original_size: usize,
     pub compressed_size: usize,
     pub compression_ratio: f64,
+    /// What greedy parsing gets with the same parameters, sent along with optimal parsing.
+    pub greedy_compressed_size: Option<usize>,
     pub tokens: Option<Vec<Token>>,
 }
 
@@ -50,11 +91,7 @@ pub struct DecodeResponse {
 }
 
 #[derive(Debug, Serialize)]
-pub struct CompressionStats {
-    pub offset_bits: u8,
-    pub length_bits: u8,
-    pub original_size: usize,
-    pub compressed_size: usize,
-    pub compression_ratio: f64,
-    pub encoding_time_ms: u128,
+pub struct ErrorResponse {
+    pub error: String,
+    pub kind: String,
 }
@@ -8,6 +8,11 @@ const encodeFileInput = document.getElementById("encode-file");
 const encodeFileInfo = document.getElementById("encode-file-info");
 const offsetBitsInput = document.getElementById("offset-bits");
 const lengthBitsInput = document.getElementById("length-bits");
+const chainDepthInput = document.getElementById("chain-depth");
+const lazyDepthInput = document.getElementById("lazy-depth");
+const optimalParsingCheckbox = document.getElementById("optimal-parsing");
+const tokenFormatSelect = document.getElementById("token-format");
+const minMatchLengthInput = document.getElementById("min-match-length");
 const displayTokensCheckbox = document.getElementById("display-tokens");
 const encodeBtn = document.getElementById("encode-btn");
 const encodeResult = document.getElementById("encode-result");
@@ -33,6 +38,7 @@ decodeBtn.addEventListener("click", handleDecode);
 downloadDecodedBtn.addEventListener("click", handleDownloadDecoded);
 
 displayTokensCheckbox.addEventListener("change", handleDisplayTokensToggle);
+tokenFormatSelect.addEventListener("change", handleTokenFormatChange);
 
 function handleEncodeFileSelect(event) {
   const file = event.target.files[0];
@@ -82,17 +88,41 @@ async function handleEncode() {
 
   const offsetBits = parseInt(offsetBitsInput.value);
   const lengthBits = parseInt(lengthBitsInput.value);
+  const chainDepth = parseInt(chainDepthInput.value);
+  const lazyDepth = parseInt(lazyDepthInput.value);
+  const minMatchLength = minMatchLengthInput.value
+    ? parseInt(minMatchLengthInput.value)
+    : null;
 
   if (offsetBits < 2 || offsetBits > 15) {
     showError("Offset bits must be between 2 and 15");
     return;
   }
 
-  if (lengthBits < 2 || lengthBits > 7) {
+  if (!lengthBitsInput.disabled && (lengthBits < 2 || lengthBits > 7)) {
     showError("Length bits must be between 2 and 7");
     return;
   }
 
+  if (!(chainDepth >= 1)) {
+    showError("Match search depth must be at least 1");
+    return;
+  }
+
+  if (!(lazyDepth >= 0)) {
+    showError("Lazy match depth cannot be negative");
+    return;
+  }
+
+  const shortestMatch = tokenFormatSelect.value === "deflate" ? 3 : 1;
+  if (
+    minMatchLength !== null &&
+    !(minMatchLength >= shortestMatch && minMatchLength <= 15)
+  ) {
+    showError(`Minimum match length must be between ${shortestMatch} and 15`);
+    return;
+  }
+
   showLoading();
 
   try {
@@ -106,12 +136,18 @@ async function handleEncode() {
         file_data: Array.from(encodeFileData.data),
         offset_bits: offsetBits,
         length_bits: lengthBits,
+        chain_depth: chainDepth,
+        lazy_depth: lazyDepth,
+        optimal_parsing: optimalParsingCheckbox.checked,
+        token_format: tokenFormatSelect.value,
+        min_match_length: minMatchLength,
         display_tokens: displayTokensCheckbox.checked,
       }),
     });
 
     if (!response.ok) {
-      throw new Error("Encoding failed");
+      const failure = await response.json().catch(() => ({}));
+      throw new Error(failure.error || "Encoding failed");
     }
 
     const result = await response.json();
@@ -145,7 +181,8 @@ async function handleDecode() {
     });
 
     if (!response.ok) {
-      throw new Error("Decoding failed");
+      const failure = await response.json().catch(() => ({}));
+      throw new Error(failure.error || "Decoding failed");
     }
 
     const result = await response.json();
@@ -173,6 +210,21 @@ function displayEncodeResult(result) {
   document.getElementById("compression-ratio").textContent =
     result.compression_ratio.toFixed(2);
 
+  const greedySizeRow = document.getElementById("greedy-size-row");
+  if (result.greedy_compressed_size != null) {
+    document.getElementById("greedy-size").textContent = formatBytes(
+      result.greedy_compressed_size
+    );
+    document.getElementById("parsing-gap").textContent = (
+      ((result.greedy_compressed_size - result.compressed_size) /
+        Math.max(result.compressed_size, 1)) *
+      100
+    ).toFixed(2);
+    greedySizeRow.classList.remove("hidden");
+  } else {
+    greedySizeRow.classList.add("hidden");
+  }
+
   lastTokens = result.tokens && result.tokens.length > 0 ? result.tokens : null;
   updateTokensDisplay();
 
@@ -187,7 +239,7 @@ function updateTokensDisplay() {
       tokenItem.className = "token-item";
       tokenItem.textContent = `Token ${index + 1}: (offset=${
         token.offset
-      }, length=${token.match_length}, char=${token.next_char})`;
+      }, length=${token.match_length}, char=${token.next_char ?? "-"})`;
       tokensList.appendChild(tokenItem);
     });
     tokensDisplay.classList.remove("hidden");
@@ -200,6 +252,11 @@ function handleDisplayTokensToggle() {
   updateTokensDisplay();
 }
 
+function handleTokenFormatChange() {
+  // DEFLATE codes match lengths up to 258 through its own tables.
+  lengthBitsInput.disabled = tokenFormatSelect.value === "deflate";
+}
+
 function displayDecodeResult(result) {
   document.getElementById("decoded-filename").textContent =
     result.decoded_filename;